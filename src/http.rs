@@ -0,0 +1,240 @@
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use serde_json::{Value, json};
+use tokio::sync::mpsc::UnboundedSender;
+use rust_roon_api::transport::{Output, Zone};
+
+use crate::{Action, GroupingSettings, POWER_ON_DELAY, Preset, Trigger, describe_presets, format_age, is_verbose, resolve_room_sets};
+
+const REPLY_TIMEOUT: Duration = Duration::from_secs(10);
+const STREAM_TIMEOUT: Duration = Duration::from_secs(5);
+const DEFAULT_BIND: &str = "127.0.0.1";
+const MAX_BODY_LEN: usize = 64 * 1024;
+
+#[derive(Clone)]
+pub struct HttpState {
+    pub settings: Arc<Mutex<GroupingSettings>>,
+    pub outputs: Arc<Mutex<HashMap<String, Output>>>,
+    pub zones: Arc<Mutex<HashMap<String, Zone>>>,
+    pub status: Arc<Mutex<String>>,
+    pub status_changed: Arc<Mutex<SystemTime>>,
+    pub requests: UnboundedSender<(Trigger, std::sync::mpsc::Sender<String>)>
+}
+
+struct Request {
+    method: String,
+    path: String,
+    token: Option<String>,
+    body: String
+}
+
+// Starts the HTTP interface when the HTTP_PORT environment variable is set, only reachable
+// from this host unless HTTP_BIND says otherwise
+pub fn start_from_env(state: HttpState) {
+    let port = match std::env::var("HTTP_PORT") {
+        Ok(port) => port,
+        Err(_) => return
+    };
+    let bind = std::env::var("HTTP_BIND").unwrap_or_else(|_| DEFAULT_BIND.to_owned());
+    let token = std::env::var("HTTP_TOKEN").ok().filter(|token| !token.is_empty());
+
+    match port.parse::<u16>() {
+        Ok(port) => {
+            match TcpListener::bind((bind.as_str(), port)) {
+                Ok(listener) => {
+                    println!("HTTP interface listening on {}:{}", bind, port);
+
+                    if token.is_none() && bind != DEFAULT_BIND {
+                        println!("HTTP interface is reachable from the network without a token, set HTTP_TOKEN to require one");
+                    }
+
+                    thread::spawn(move || {
+                        for stream in listener.incoming() {
+                            match stream {
+                                Ok(stream) => handle_connection(stream, &state, token.as_deref()),
+                                Err(err) => println!("HTTP connection failed: {}", err)
+                            }
+                        }
+                    });
+                }
+                Err(err) => println!("Failed to start HTTP interface on {}:{}: {}", bind, port, err)
+            }
+        }
+        Err(_) => println!("Invalid HTTP_PORT: {}", port)
+    }
+}
+
+fn read_request(stream: &TcpStream) -> Option<Request> {
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+
+    reader.read_line(&mut line).ok()?;
+
+    let mut parts = line.split_whitespace();
+    let method = parts.next()?.to_owned();
+    let path = parts.next()?.to_owned();
+
+    let mut content_length = 0;
+    let mut token = None;
+
+    loop {
+        line.clear();
+
+        if reader.read_line(&mut line).ok()? == 0 || line.trim().is_empty() {
+            break
+        }
+
+        if let Some((name, value)) = line.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse().ok()?;
+            } else if name.trim().eq_ignore_ascii_case("authorization") {
+                token = value.trim().strip_prefix("Bearer ").map(|token| token.trim().to_owned());
+            }
+        }
+    }
+
+    // A body this large is no preset name, don't allocate for it
+    if content_length > MAX_BODY_LEN {
+        return None
+    }
+
+    let mut body = vec![0; content_length];
+
+    reader.read_exact(&mut body).ok()?;
+
+    let body = String::from_utf8(body).ok()?;
+
+    Some(Request { method, path, token, body })
+}
+
+fn handle_connection(mut stream: TcpStream, state: &HttpState, token: Option<&str>) {
+    // A client that stops sending or reading must not block the listener
+    if stream.set_read_timeout(Some(STREAM_TIMEOUT)).is_err() || stream.set_write_timeout(Some(STREAM_TIMEOUT)).is_err() {
+        return
+    }
+
+    let (code, body) = match read_request(&stream) {
+        Some(request) if token.is_some() && request.token.as_deref() != token => {
+            (401, json!({"error": "Unauthorized"}))
+        }
+        Some(request) => match (request.method.as_str(), request.path.as_str()) {
+            ("GET", "/presets") => (200, get_presets(state)),
+            ("GET", "/status") => (200, get_status(state)),
+            ("GET", "/zones") => (200, get_zones(state)),
+            ("POST", "/activate") => post_action(state, &request.body, Action::Activate),
+            ("POST", "/deactivate") => post_action(state, &request.body, Action::Deactivate),
+            _ => (404, json!({"error": "Not found"}))
+        },
+        None => (400, json!({"error": "Bad request"}))
+    };
+
+    write_response(&mut stream, code, &body);
+}
+
+fn get_presets(state: &HttpState) -> Value {
+    let presets = state.settings.lock().unwrap().presets.to_owned();
+    let summaries = describe_presets(&presets, &state.outputs.lock().unwrap());
+
+    serde_json::to_value(summaries).unwrap_or_default()
+}
+
+fn get_zones(state: &HttpState) -> Value {
+    let zones: Vec<Value> = state.zones.lock().unwrap()
+        .values()
+        .map(|zone| {
+            let outputs: Vec<&str> = zone.outputs.iter().map(|output| output.display_name.as_str()).collect();
+
+            json!({"name": zone.display_name, "outputs": outputs})
+        })
+        .collect();
+
+    Value::Array(zones)
+}
+
+fn get_status(state: &HttpState) -> Value {
+    let status = state.status.lock().unwrap().to_owned();
+    let changed = *state.status_changed.lock().unwrap();
+    let last_changed = changed.duration_since(UNIX_EPOCH).map_or(0, |duration| duration.as_secs());
+    let status = if is_verbose() {
+        format!("{} ({})", status, format_age(changed))
+    } else {
+        status
+    };
+
+    json!({"status": status, "last_changed": last_changed})
+}
+
+fn post_action(state: &HttpState, body: &str, action: Action) -> (u16, Value) {
+    let preset_name = serde_json::from_str::<Value>(body)
+        .ok()
+        .and_then(|body| body["preset"].as_str().map(|preset_name| preset_name.to_owned()));
+    let preset_name = match preset_name {
+        Some(preset_name) => preset_name,
+        None => return (400, json!({"error": "Expected a body like {\"preset\": \"<name>\"}"}))
+    };
+    let reply_timeout = {
+        let settings = state.settings.lock().unwrap();
+
+        resolve_room_sets(&settings.presets, &settings.room_sets)
+            .iter()
+            .find(|preset| preset.name == preset_name)
+            .map(|preset| REPLY_TIMEOUT + planned_duration(preset, &action))
+    };
+    let reply_timeout = match reply_timeout {
+        Some(reply_timeout) => reply_timeout,
+        None => return (404, json!({"error": format!("Unknown preset \"{}\"", preset_name)}))
+    };
+
+    let (reply_tx, reply_rx) = std::sync::mpsc::channel();
+    let trigger = Trigger { preset_name, action };
+
+    if state.requests.send((trigger, reply_tx)).is_err() {
+        return (500, json!({"error": "Extension is shutting down"}))
+    }
+
+    match reply_rx.recv_timeout(reply_timeout) {
+        Ok(status) => (200, json!({"status": status})),
+        Err(_) => (500, json!({"error": "No response from the extension"}))
+    }
+}
+
+// The reply follows the last command, fades, staggers and delays hold it back for as long as they take
+fn planned_duration(preset: &Preset, action: &Action) -> Duration {
+    if !matches!(action, Action::Activate) {
+        return Duration::ZERO
+    }
+
+    let staggered = preset.output_ids.len().saturating_sub(1) as u64;
+    let planned_ms = preset.stagger_ms.unwrap_or(0) as u64 * staggered
+        + preset.volume_delay_ms.unwrap_or(0) as u64
+        + preset.fade_ms.unwrap_or(0) as u64;
+    let power_on = if preset.power_on_members { POWER_ON_DELAY } else { Duration::ZERO };
+
+    Duration::from_millis(planned_ms) + power_on
+}
+
+fn write_response(stream: &mut TcpStream, code: u16, body: &Value) {
+    let reason = match code {
+        200 => "OK",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        404 => "Not Found",
+        _ => "Internal Server Error"
+    };
+    let body = body.to_string();
+    let response = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        code,
+        reason,
+        body.len(),
+        body
+    );
+
+    if let Err(err) = stream.write_all(response.as_bytes()) {
+        println!("Failed to send HTTP response: {}", err);
+    }
+}
@@ -1,12 +1,53 @@
 use std::collections::HashMap;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::fs::OpenOptions;
+use std::future::Future;
+use std::io::Write;
 use std::sync::{Arc, Mutex};
+use std::task::Poll;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::sync::mpsc;
 use serde::{Deserialize, Serialize};
 use serde_json::{Value, json};
 use serde_repr::{Deserialize_repr, Serialize_repr};
 use rust_roon_api::{RoonApi, CoreEvent, Info, Parsed, RespProps, Services, Svc, send_complete, send_continue_all, info};
+use rust_roon_api::browse::{Browse, BrowseOpts, LoadOpts};
 use rust_roon_api::status::{self, Status};
 use rust_roon_api::settings::{self, Settings, Widget, Dropdown, Group, Label, Layout, Textbox, Integer};
-use rust_roon_api::transport::{Transport, Output, Zone};
+use rust_roon_api::transport::{Transport, Output, State, Zone};
+use rust_roon_api::transport::volume::{Mute, Scale, Volume};
+
+#[cfg(feature = "http")]
+mod http;
+
+const ACTION_DEBOUNCE: Duration = Duration::from_secs(2);
+const LEGACY_SETTINGS_KEY: &str = "settings";
+const STAGGER_MS_MAX: u32 = 10000;
+const VOLUME_DELAY_MS_MAX: u32 = 10000;
+const WAIT_FOR_OUTPUTS_MS_MAX: u32 = 30000;
+const FADE_MS_MAX: u32 = 10000;
+const STATUS_GRACE_MS_MAX: u32 = 30000;
+const COOLDOWN_SECONDS_MAX: u32 = 3600;
+const FADE_STEPS: u32 = 10;
+const MASTER_SCALE_MIN: u32 = 50;
+const MASTER_SCALE_MAX: u32 = 150;
+const PRIORITY_MIN: i32 = -100;
+const PRIORITY_MAX: i32 = 100;
+const GROUP_TIMEOUT: Duration = Duration::from_secs(10);
+const EXIT_TIMEOUT: Duration = Duration::from_secs(3);
+const GROUP_LATENCY_SAMPLES: usize = 10;
+const POWER_ON_DELAY: Duration = Duration::from_secs(2);
+const CORE_SEARCH_INTERVAL: Duration = Duration::from_secs(30);
+const VOLUME_RETRIES: u32 = 3;
+const VOLUME_RETRY_DELAY: Duration = Duration::from_secs(1);
+const LAST_VOLUMES_SAVE_DELAY: Duration = Duration::from_secs(5);
+const AUTO_MATCH_DISABLED: &str = "Automatic matching disabled";
+const STATUS_ACTIVATED: &str = "Preset \"{preset}\" activated";
+const STATUS_DEACTIVATED: &str = "Preset \"{preset}\" deactivated, {count} outputs ungrouped";
+const STATUS_MATCHED: &str = "Grouped zone \"{zone}\" represents the \"{preset}\" preset ({state})";
+const RADIO_HIERARCHY: &str = "internet_radio";
+const RADIO_LOAD_COUNT: usize = 100;
 
 #[derive(Clone, Debug, Default, Deserialize_repr, Serialize_repr)]
 #[repr(usize)]
@@ -15,7 +56,32 @@ enum Action {
     #[default] Edit = 0,
     Activate = 1,
     Deactivate = 2,
-    Delete = 3
+    Delete = 3,
+    UngroupAll = 4,
+    CopyVolume = 5,
+    RemoveProfile = 6,
+    ApplyVolumes = 7,
+    FindDuplicates = 8,
+    MergeDuplicates = 9,
+    ReplaceOutput = 10,
+    SetVolumeTypeAll = 11,
+    ConfirmVolumeTypeAll = 12,
+    ResetVolumes = 13,
+    ActivateBatch = 14,
+    ToggleMute = 15,
+    Arm = 16,
+    Disarm = 17,
+    DumpDiagnostics = 18,
+    CompactPresets = 19,
+    Favorite = 20,
+    Unfavorite = 21
+}
+
+impl Action {
+    // Only these reach the transport, saving any other action just stores the settings
+    fn is_execution(&self) -> bool {
+        matches!(self, Action::Activate | Action::Deactivate | Action::UngroupAll | Action::ApplyVolumes | Action::ActivateBatch | Action::ToggleMute)
+    }
 }
 
 #[derive(Clone, Debug, Default, Deserialize_repr, Serialize_repr)]
@@ -27,638 +93,6154 @@ enum VolumeType {
     Preset = 2
 }
 
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum PresetSelection {
+    None,
+    Existing(usize),
+    New
+}
+
 #[derive(Clone, Debug, Default, Deserialize, Serialize)]
 struct Preset {
     name: String,
     output_ids: Vec<String>,
     volume_type: VolumeType,
-    volumes: HashMap<String, i32>
+    volumes: HashMap<String, f32>,
+    #[serde(default)]
+    volume_master: Option<String>,
+    #[serde(default)]
+    stagger_ms: Option<u32>,
+    #[serde(default)]
+    volume_delay_ms: Option<u32>,
+    #[serde(default)]
+    wait_for_outputs_ms: Option<u32>,
+    #[serde(default)]
+    fade_ms: Option<u32>,
+    #[serde(default)]
+    cooldown_seconds: Option<u32>,
+    #[serde(default)]
+    master_scale: Option<f32>,
+    #[serde(default)]
+    volume_as_percent: bool,
+    #[serde(default)]
+    volume_percents: HashMap<String, f32>,
+    #[serde(default)]
+    exclusive: bool,
+    #[serde(default)]
+    power_on_members: bool,
+    #[serde(default)]
+    mirror: bool,
+    #[serde(default)]
+    apply_volume_on_match: bool,
+    #[serde(default)]
+    output_names: HashMap<String, String>,
+    #[serde(default)]
+    volume_profiles: HashMap<String, HashMap<String, f32>>,
+    #[serde(default)]
+    active_profile: Option<String>,
+    #[serde(default)]
+    play_item: Option<String>,
+    #[serde(default)]
+    room_set: Option<String>,
+    #[serde(default)]
+    priority: i32,
+    #[serde(default)]
+    zone_label: Option<String>,
+    #[serde(default)]
+    activation_count: u64,
+    #[serde(default)]
+    last_activated_at: Option<String>,
+    #[serde(default)]
+    armed: Option<bool>,
+    #[serde(default)]
+    favorite: bool
 }
 
 #[derive(Clone, Debug, Default, Deserialize, Serialize)]
-struct GroupingSettings {
-    selected: Option<usize>,
-    action: Action,
-    add: Option<String>,
-    primary_output_id: Option<String>,
-    volume_output_id: Option<String>,
-    volume_level: String,
+struct RoomSet {
     name: String,
-    output_ids: Vec<String>,
-    volume_type: VolumeType,
-    presets: Vec<Preset>,
-    extracted_preset: Option<Preset>
+    output_ids: Vec<String>
 }
 
-fn store_preset(settings: &mut GroupingSettings) -> Option<()> {
-    let name = settings.name.to_owned();
-    let add = settings.add.to_owned()?;
-    let primary_output_id = settings.primary_output_id.to_owned()?;
-    let mut output_ids = settings.output_ids.to_owned();
+impl Preset {
+    // The output put first when grouping, the volume master if one is set and otherwise the primary
+    fn leading_output_id(&self) -> Option<&String> {
+        self.volume_master
+            .as_ref()
+            .filter(|volume_master| self.output_ids.contains(volume_master))
+            .or_else(|| self.output_ids.first())
+    }
 
-    if output_ids.len() == 0 {
-        output_ids.push(primary_output_id.to_owned());
-        settings.output_ids.push(primary_output_id);
+    fn is_armed(&self) -> bool {
+        // Presets from before this setting existed are armed
+        self.armed.unwrap_or(true)
     }
 
-    if !output_ids.contains(&add) {
-        output_ids.push(add.to_owned());
-        settings.output_ids.push(add);
+    fn active_volumes(&self) -> &HashMap<String, f32> {
+        // The stored volumes apply when no profile is selected
+        self.active_profile
+            .as_ref()
+            .and_then(|profile| self.volume_profiles.get(profile))
+            .unwrap_or(&self.volumes)
     }
 
-    if name.len() > 0 && output_ids.len() > 0 {
-        let preset = Preset {
-            name,
-            output_ids,
-            ..Default::default()
-        };
+    fn active_volumes_mut(&mut self) -> &mut HashMap<String, f32> {
+        match self.active_profile.as_ref().and_then(|profile| self.volume_profiles.get_mut(profile)) {
+            Some(volumes) => volumes,
+            None => &mut self.volumes
+        }
+    }
+
+    // Percentages are kept for the stored volumes only, a profile holds native levels
+    fn is_profile_active(&self) -> bool {
+        self.active_profile.as_ref().map_or(false, |profile| self.volume_profiles.contains_key(profile))
+    }
 
-        if let Some(selected) = settings.selected {
-            let preset_count = settings.presets.len();
+    fn replace_output_id(&mut self, old_id: &str, new_id: &str) {
+        for output_id in self.output_ids.iter_mut().filter(|output_id| *output_id == old_id) {
+            *output_id = new_id.to_owned();
+        }
 
-            if selected < preset_count {
-                settings.presets[selected] = preset;
-            } else {
-                settings.selected = Some(preset_count);
-                settings.presets.push(preset);
+        if let Some(volume) = self.volumes.remove(old_id) {
+            self.volumes.insert(new_id.to_owned(), volume);
+        }
+
+        if let Some(percent) = self.volume_percents.remove(old_id) {
+            self.volume_percents.insert(new_id.to_owned(), percent);
+        }
+
+        if let Some(name) = self.output_names.remove(old_id) {
+            self.output_names.insert(new_id.to_owned(), name);
+        }
+
+        for volumes in self.volume_profiles.values_mut() {
+            if let Some(volume) = volumes.remove(old_id) {
+                volumes.insert(new_id.to_owned(), volume);
             }
         }
 
-        Some(())
-    } else {
-        None
+        if self.volume_master.as_deref() == Some(old_id) {
+            self.volume_master = Some(new_id.to_owned());
+        }
     }
 }
 
-fn store_volume(settings: &mut GroupingSettings, outputs: &HashMap<String, Output>) -> Option<()> {
-    let selected = settings.selected?;
-    let preset = settings.presets.get_mut(selected)?;
+#[derive(Clone, Debug, Serialize)]
+pub struct PresetSummary {
+    pub name: String,
+    pub members: Vec<String>,
+    pub volume_mode: String,
+    pub active: bool
+}
 
-    preset.volume_type = settings.volume_type.to_owned();
+// A preset action requested through the HTTP interface or resumed after waiting
+#[derive(Clone, Debug)]
+struct Trigger {
+    preset_name: String,
+    action: Action
+}
 
-    if let VolumeType::Preset = settings.volume_type {
-        let volume_output_id = settings.volume_output_id.as_ref()?;
+#[derive(Clone, Debug)]
+struct MatchedZone {
+    zone_id: String,
+    zone_name: String,
+    preset_name: String,
+    master_name: Option<String>,
+    status_msg: String
+}
 
-        if let None = preset.volumes.get(volume_output_id) {
-            let volume = outputs.get(volume_output_id)?.volume.as_ref()?;
+#[derive(Clone, Debug)]
+struct PendingGroup {
+    preset_name: String,
+    output_ids: Vec<String>,
+    volume_ids: Vec<String>,
+    started: Instant,
+    deadline: tokio::time::Instant
+}
 
-            settings.volume_level = volume.value.to_string();
-        }
+// An activation postponed until its members come online
+#[derive(Clone, Debug)]
+struct PendingWait {
+    preset_name: String,
+    output_ids: Vec<String>,
+    deadline: tokio::time::Instant
+}
 
-        if let Ok(volume_level) = settings.volume_level.parse::<i32>() {
-            preset.volumes.insert(volume_output_id.to_owned(), volume_level);
+#[derive(Clone, Debug)]
+struct PendingVolume {
+    target: f32,
+    step: f32,
+    attempts: u32,
+    deadline: tokio::time::Instant
+}
 
-            return Some(())
-        }
-    }
+#[derive(Clone, Debug, PartialEq)]
+enum PlayStep {
+    Station,
+    Action
+}
 
-    None
+#[derive(Clone, Debug)]
+struct PendingPlay {
+    preset_name: String,
+    station: String,
+    output_id: String,
+    step: PlayStep
 }
 
-fn load_preset(settings: &mut GroupingSettings, outputs: &HashMap<String, Output>) {
-    if let Some(selected) = settings.selected {
-        if let Some(preset) = settings.presets.get_mut(selected) {
-            settings.name = preset.name.to_owned();
-            settings.primary_output_id = Some(preset.output_ids[0].to_owned());
-            settings.output_ids = preset.output_ids.to_owned();
-            settings.add = None;
-            settings.volume_type = preset.volume_type.to_owned();
+#[derive(Clone, Debug, PartialEq)]
+enum TransportCommand {
+    Group(Vec<String>),
+    Ungroup(Vec<String>),
+    // The level and the step of the output, a fractional level is reached in steps
+    ChangeVolume(String, f32, f32),
+    Mute(String, bool),
+    PowerOn(String, String),
+    Wait(Duration)
+}
 
-            if let VolumeType::Preset = settings.volume_type {
-                if let Some(volume_output_id) = &settings.volume_output_id {
-                    if let Some(volume_level) = preset.volumes.get(volume_output_id).cloned() {
-                        settings.volume_level = volume_level.to_string();
-                    } else if let Some(output) = outputs.get(volume_output_id) {
-                        if let Some(volume) = output.volume.as_ref() {
-                            let volume_level = volume.value as i32;
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum StoreError {
+    NoOutputToAdd,
+    NoPrimaryOutput,
+    NoName,
+    OutputUnavailable,
+    InvalidVolumeLevel
+}
 
-                            preset.volumes.insert(volume_output_id.to_owned(), volume_level);
-                            settings.volume_level = volume_level.to_string();
-                        }
-                    }
-                }
-            }
-        } else if let Some(preset) = settings.extracted_preset.as_ref() {
-            settings.name = preset.name.to_owned();
-            settings.primary_output_id = Some(preset.output_ids[0].to_owned());
-            settings.output_ids = preset.output_ids.to_owned();
-            settings.action = Action::Edit;
-            settings.add = settings.output_ids.get(0).cloned();
-            settings.volume_type = VolumeType::Untouched;
-        } else {
-            settings.name = String::new();
-            settings.primary_output_id = None;
-            settings.output_ids = Vec::new();
-            settings.action = Action::Edit;
-            settings.add = None;
-            settings.volume_type = VolumeType::Untouched;
+impl StoreError {
+    // The widget that shows the error
+    fn setting(&self) -> &'static str {
+        match self {
+            StoreError::NoOutputToAdd => "add",
+            StoreError::NoPrimaryOutput => "primary_output_id",
+            StoreError::NoName => "name",
+            StoreError::OutputUnavailable => "volume_output_id",
+            StoreError::InvalidVolumeLevel => "volume_level"
         }
     }
-}
-
-fn match_preset<'a, 'b>(presets: &'a Vec<Preset>, zones: &'b Vec<Zone>) -> Option<(&'a Preset, &'b Zone)> {
-    for preset in presets {
-        for zone in zones {
-            if zone.outputs.len() == preset.output_ids.len() {
-                let output_ids: Vec<&str> = zone.outputs
-                    .iter()
-                    .map(|output| output.output_id.as_str())
-                    .collect();
-                let match_count = preset.output_ids.iter().zip(output_ids).filter(|(a, b)| a == b).count();
 
-                if match_count == preset.output_ids.len() {
-                    return Some((preset, zone))
-                }
-            }
+    fn message(&self) -> &'static str {
+        match self {
+            StoreError::NoOutputToAdd => "Select an output to group with",
+            StoreError::NoPrimaryOutput => "Select the primary output",
+            StoreError::NoName => "Enter a name to save the preset",
+            StoreError::OutputUnavailable => "The output is unavailable, its volume can't be stored",
+            StoreError::InvalidVolumeLevel => "Enter a volume level to store it"
         }
     }
+}
 
-    None
+struct StatusReporter {
+    status: Option<Status>,
+    message: Arc<Mutex<String>>,
+    changed: Arc<Mutex<SystemTime>>,
+    verbose: bool
 }
 
-fn extract_preset(zones: &Vec<Zone>) -> Option<Preset> {
-    for zone in zones {
-        if zone.outputs.len() > 1 {
-            let mut preset = Preset::default();
+impl StatusReporter {
+    async fn set_status(&self, message: String, is_error: bool) {
+        let previous = std::mem::replace(&mut *self.changed.lock().unwrap(), SystemTime::now());
 
-            preset.name = zone.display_name.to_owned();
+        if self.verbose {
+            println!("Status: {} (previous status set {})", message, format_age(previous));
+        }
 
-            for output in &zone.outputs {
-                preset.output_ids.push(output.output_id.to_owned());
-            }
+        *self.message.lock().unwrap() = message.to_owned();
 
-            return Some(preset)
+        if let Some(status) = self.status.as_ref() {
+            status.set_status(message, is_error).await;
         }
     }
+}
 
-    None
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+struct GroupingSettings {
+    selected: Option<usize>,
+    action: Action,
+    add: Option<String>,
+    primary_output_id: Option<String>,
+    volume_output_id: Option<String>,
+    volume_level: String,
+    name: String,
+    output_ids: Vec<String>,
+    volume_type: VolumeType,
+    presets: Vec<Preset>,
+    extracted_preset: Option<Preset>,
+    #[serde(default)]
+    force: bool,
+    #[serde(default)]
+    volume_master: Option<String>,
+    #[serde(default)]
+    deactivate_on_exit: bool,
+    #[serde(default)]
+    stagger_ms: String,
+    #[serde(default)]
+    volume_delay_ms: String,
+    #[serde(default)]
+    wait_for_outputs_ms: String,
+    #[serde(default)]
+    fade_ms: String,
+    #[serde(default)]
+    cooldown_seconds: String,
+    #[serde(default)]
+    master_scale: String,
+    #[serde(default)]
+    auto_name: bool,
+    #[serde(default)]
+    volume_as_percent: bool,
+    #[serde(default)]
+    exclusive: bool,
+    #[serde(default)]
+    power_on_members: bool,
+    #[serde(default)]
+    mirror: bool,
+    #[serde(default)]
+    apply_volume_on_match: bool,
+    #[serde(default)]
+    match_by_name_fallback: bool,
+    #[serde(default)]
+    active_profile: Option<String>,
+    #[serde(default)]
+    new_profile: String,
+    #[serde(default)]
+    auto_match_enabled: Option<bool>,
+    #[serde(default)]
+    filter: String,
+    #[serde(default)]
+    play_item: String,
+    #[serde(default)]
+    advanced_mode: bool,
+    #[serde(default)]
+    room_set: Option<String>,
+    #[serde(default)]
+    room_sets: Vec<RoomSet>,
+    #[serde(default)]
+    room_set_selected: Option<usize>,
+    #[serde(default)]
+    room_set_name: String,
+    #[serde(default)]
+    room_set_add: Option<String>,
+    #[serde(default)]
+    room_set_remove: Option<String>,
+    #[serde(default)]
+    priority: String,
+    #[serde(default)]
+    zone_label: String,
+    #[serde(default)]
+    volume_output_cleared: bool,
+    #[serde(default)]
+    copy_clamped: Vec<String>,
+    #[serde(default)]
+    replace_from: Option<String>,
+    #[serde(default)]
+    replace_to: Option<String>,
+    #[serde(default)]
+    status_activated: String,
+    #[serde(default)]
+    status_deactivated: String,
+    #[serde(default)]
+    status_matched: String,
+    #[serde(default)]
+    batch: Vec<usize>,
+    #[serde(default)]
+    batch_add: Option<usize>,
+    #[serde(default)]
+    batch_remove: Option<usize>,
+    #[serde(default)]
+    output_aliases: HashMap<String, String>,
+    #[serde(default)]
+    alias_output_id: Option<String>,
+    #[serde(default)]
+    alias_name: String,
+    #[serde(default)]
+    alias_remove: Option<String>,
+    #[serde(default)]
+    refuse_busy_outputs: bool,
+    #[serde(default)]
+    status_grace_ms: String,
+    #[serde(default)]
+    safety_min: String,
+    #[serde(default)]
+    safety_max: String,
+    #[serde(default)]
+    store_errors: Vec<(String, String)>
 }
 
-fn make_layout(settings: GroupingSettings, outputs: &HashMap<String, Output>) -> Layout<GroupingSettings> {
-    let has_error = false;
-    let is_selected = settings.selected.is_some();
-    let mut widgets = Vec::new();
-    let mut preset_list = vec![HashMap::from([ ("title", "(select preset)".into()), ("value", Value::Null) ])];
+impl GroupingSettings {
+    fn selection(&self) -> PresetSelection {
+        match self.selected {
+            Some(index) if index < self.presets.len() => PresetSelection::Existing(index),
+            Some(index) if index == self.presets.len() => PresetSelection::New,
+            // Left over from a list that has since shrunk
+            _ => PresetSelection::None
+        }
+    }
 
-    for index in 0..settings.presets.len() {
-        let name = settings.presets[index].name.to_owned();
+    // The alias set in the extension takes precedence over the name Roon uses
+    fn output_name<'a>(&'a self, output: &'a Output) -> &'a str {
+        aliased_name(&self.output_aliases, &output.output_id, &output.display_name)
+    }
 
-        if name.len() > 0 {
-            preset_list.push(HashMap::from([ ("title", name.into()), ("value", index.into()) ]));
+    fn safety_cap(&self) -> SafetyCap {
+        SafetyCap {
+            min_percent: self.safety_min.trim().parse().ok(),
+            max_percent: self.safety_max.trim().parse().ok()
         }
     }
 
-    preset_list.push(HashMap::from([ ("title", "New Preset".into()), ("value", settings.presets.len().into()) ]));
+    fn is_auto_match_enabled(&self) -> bool {
+        // Configurations from before this setting existed keep matching
+        self.auto_match_enabled.unwrap_or(true)
+    }
 
-    let selected = Widget::Dropdown(Dropdown {
-        title: "Preset",
-        subtitle: None,
-        values: preset_list,
-        setting: "selected"
-    });
+    fn replace_output_id(&mut self, old_id: &str, new_id: &str) {
+        for output_id in self.output_ids.iter_mut().filter(|output_id| *output_id == old_id) {
+            *output_id = new_id.to_owned();
+        }
 
-    widgets.push(selected);
+        for output_id in [&mut self.primary_output_id, &mut self.add, &mut self.volume_output_id, &mut self.volume_master] {
+            if output_id.as_deref() == Some(old_id) {
+                *output_id = Some(new_id.to_owned());
+            }
+        }
+    }
 
-    if is_selected {
-        let is_new_preset = settings.selected.unwrap() == settings.presets.len();
+    fn validate(&self, outputs: &HashMap<String, Output>) -> Vec<(String, String)> {
+        let mut errors = Vec::new();
 
-        if !is_new_preset {
-            let mut actions = Vec::new();
+        if let Action::Edit = self.action {
+            let delays = [
+                ("stagger_ms", &self.stagger_ms, "Stagger delay", STAGGER_MS_MAX),
+                ("volume_delay_ms", &self.volume_delay_ms, "Volume delay", VOLUME_DELAY_MS_MAX),
+                ("wait_for_outputs_ms", &self.wait_for_outputs_ms, "Wait for outputs", WAIT_FOR_OUTPUTS_MS_MAX),
+                ("fade_ms", &self.fade_ms, "Fade time", FADE_MS_MAX)
+            ];
 
-            actions.push(HashMap::from([ ("title", "(select action)".into()), ("value", Value::Null) ]));
-            actions.push(HashMap::from([ ("title", "Activate".into()), ("value", (Action::Activate as usize).into()) ]));
-            actions.push(HashMap::from([ ("title", "Deactivate".into()), ("value", (Action::Deactivate as usize).into()) ]));
-            actions.push(HashMap::from([ ("title", "Edit".into()), ("value", (Action::Edit as usize).into()) ]));
-            actions.push(HashMap::from([ ("title", "Delete".into()), ("value", (Action::Delete as usize).into()) ]));
+            for (setting, value, title, max) in delays {
+                let is_valid = match value.parse::<u32>() {
+                    Ok(delay_ms) => delay_ms <= max,
+                    Err(_) => value.is_empty()
+                };
 
-            let action = Widget::Dropdown(Dropdown {
-                title: "Action",
-                subtitle: None,
-                values: actions,
-                setting: "action"
-            });
+                if !is_valid {
+                    let err_msg = format!("{} should be between 0 and {} ms", title, max);
 
-            widgets.push(action);
-        }
+                    errors.push((setting.to_owned(), err_msg));
+                }
+            }
 
-        match settings.action {
-            Action::Edit => {
-                let name = Widget::Textbox(Textbox {
-                    title: "Name",
-                    subtitle: None,
-                    setting: "name"
-                });
-                let mut edit_group = Widget::Group(Group {
-                    title: "Preset Editor",
-                    subtitle: None,
-                    collapsable: true,
-                    items: vec![name]
-                });
+            let is_valid = match self.master_scale.parse::<u32>() {
+                Ok(percent) => (MASTER_SCALE_MIN..=MASTER_SCALE_MAX).contains(&percent),
+                Err(_) => self.master_scale.is_empty()
+            };
 
-                if settings.name.len() > 0 {
-                    if let Widget::Group(edit_group) = &mut edit_group {
-                        let mut values = vec![HashMap::from(
-                            [ ("title", "(select output)".into()), ("value", Value::Null) ]
-                        )];
+            if !is_valid {
+                let err_msg = format!("Master scale should be between {} and {}%", MASTER_SCALE_MIN, MASTER_SCALE_MAX);
 
-                        for (output_id, output) in outputs {
-                            values.push(HashMap::from(
-                                [ ("title", output.display_name.to_owned().into()), ("value", output_id.to_owned().into()) ]
-                            ));
-                        }
+                errors.push(("master_scale".to_owned(), err_msg));
+            }
 
-                        let output = Widget::Dropdown(Dropdown {
-                            title: "Primary Output",
-                            subtitle: None,
-                            values,
-                            setting: "primary_output_id"
-                        });
+            let is_valid = match self.cooldown_seconds.parse::<u32>() {
+                Ok(cooldown_seconds) => cooldown_seconds <= COOLDOWN_SECONDS_MAX,
+                Err(_) => self.cooldown_seconds.is_empty()
+            };
 
-                        edit_group.items.push(output);
+            if !is_valid {
+                let err_msg = format!("Cooldown should be between 0 and {} s", COOLDOWN_SECONDS_MAX);
 
-                        if let Some(primary_output_id) = &settings.primary_output_id {
-                            if let Some(output) = outputs.get(primary_output_id) {
-                                let mut values = vec![HashMap::from([ ("title", "(select output)".into()), ("value", Value::Null) ])];
+                errors.push(("cooldown_seconds".to_owned(), err_msg));
+            }
 
-                                for output_id in &output.can_group_with_output_ids {
-                                    if *output_id != *primary_output_id {
-                                        let name = outputs.get(output_id).unwrap().display_name.to_owned();
+            let is_valid = match self.priority.trim().parse::<i32>() {
+                Ok(priority) => (PRIORITY_MIN..=PRIORITY_MAX).contains(&priority),
+                Err(_) => self.priority.trim().is_empty()
+            };
 
-                                        values.push(HashMap::from([ ("title", name.into()), ("value", output_id.to_owned().into()) ]));
-                                    }
-                                }
+            if !is_valid {
+                let err_msg = format!("Priority should be between {} and {}", PRIORITY_MIN, PRIORITY_MAX);
 
-                                edit_group.items.push(Widget::Dropdown(Dropdown {
-                                    title: "Group With",
-                                    subtitle: None,
-                                    values,
-                                    setting: "add"
-                                }));
-
-                                let values = vec![
-                                    HashMap::from([ ("title", "(select volume control)".into()), ("value", Value::Null) ]),
-                                    HashMap::from([ ("title", "Untouched".into()), ("value", (VolumeType::Untouched as usize).into()) ]),
-                                    HashMap::from([ ("title", "Last Used".into()), ("value", (VolumeType::LastUsed as usize).into()) ]),
-                                    HashMap::from([ ("title", "Preset".into()), ("value", (VolumeType::Preset as usize).into()) ])
-                                ];
-
-                                edit_group.items.push(Widget::Dropdown(Dropdown {
-                                    title: "Volume Levels",
-                                    subtitle: None,
-                                    values,
-                                    setting: "volume_type"
-                                }));
-
-                                if let VolumeType::Preset = settings.volume_type {
-                                    let mut values = vec![
-                                        HashMap::from([ ("title", "(select output)".into()), ("value", Value::Null) ])
-                                    ];
+                errors.push(("priority".to_owned(), err_msg));
+            }
+        }
 
-                                    for output_id in &settings.output_ids {
-                                        if let Some(output) = outputs.get(output_id) {
-                                            let name = output.display_name.to_owned();
+        if self.advanced_mode {
+            let templates = [
+                ("status_activated", &self.status_activated, &["preset"][..]),
+                ("status_deactivated", &self.status_deactivated, &["preset", "count"][..]),
+                ("status_matched", &self.status_matched, &["zone", "preset", "state", "master"][..])
+            ];
 
-                                            values.push(HashMap::from([ ("title", name.into()), ("value", output_id.to_owned().into()) ]));
-                                        }
-                                    }
+            for (setting, template, placeholders) in templates {
+                if let Some(placeholder) = unknown_placeholder(template, placeholders) {
+                    let err_msg = format!(
+                        "Unknown placeholder {{{}}}, use {}",
+                        placeholder,
+                        placeholders.iter().map(|placeholder| format!("{{{}}}", placeholder)).collect::<Vec<_>>().join(", ")
+                    );
 
-                                    edit_group.items.push(Widget::Dropdown(Dropdown {
-                                        title: "Output",
-                                        subtitle: None,
-                                        values,
-                                        setting: "volume_output_id"
-                                    }));
+                    errors.push((setting.to_owned(), err_msg));
+                }
+            }
 
-                                    if let Some(output_id) = &settings.volume_output_id {
-                                        if let Some(volume) = &outputs.get(output_id).unwrap().volume {
-                                            let mut volume_level = Integer {
-                                                title: "Output Volume",
-                                                subtitle: None,
-                                                min: volume.hard_limit_min.to_string(),
-                                                max: volume.hard_limit_max.to_string(),
-                                                setting: "volume_level",
-                                                error: None
-                                            };
-
-                                            if let Ok(out_of_range) = volume_level.out_of_range(&settings.volume_level) {
-                                                if out_of_range {
-                                                    let err_msg = format!("Volume level should be between {} and {}", volume_level.min, volume_level.max);
-
-                                                    volume_level.error = Some(err_msg);
-                                                }
-                                            }
+            let is_valid = match self.status_grace_ms.trim().parse::<u32>() {
+                Ok(grace_ms) => grace_ms <= STATUS_GRACE_MS_MAX,
+                Err(_) => self.status_grace_ms.trim().is_empty()
+            };
 
-                                            edit_group.items.push(Widget::Integer(volume_level));
-                                        }
-                                    }
-                                }
-                            }
-                        }
-                    }
+            if !is_valid {
+                let err_msg = format!("Status grace period should be between 0 and {} ms", STATUS_GRACE_MS_MAX);
+
+                errors.push(("status_grace_ms".to_owned(), err_msg));
+            }
+
+            for (setting, value) in [("safety_min", &self.safety_min), ("safety_max", &self.safety_max)] {
+                let is_valid = match value.trim().parse::<u32>() {
+                    Ok(percent) => percent <= 100,
+                    Err(_) => value.trim().is_empty()
+                };
+
+                if !is_valid {
+                    errors.push((setting.to_owned(), "Safety cap should be a percentage between 0 and 100".to_owned()));
                 }
+            }
 
-                widgets.push(edit_group);
+            let cap = self.safety_cap();
+
+            if let (Some(min_percent), Some(max_percent)) = (cap.min_percent, cap.max_percent) {
+                if min_percent > max_percent {
+                    errors.push(("safety_max".to_owned(), "Safety maximum should not be below the minimum".to_owned()));
+                }
             }
-            _ => ()
         }
 
-        if let Some(primary_output_id) = &settings.primary_output_id {
-            let name = outputs.get(primary_output_id).unwrap().display_name.to_owned();
-            let mut subtitle = String::from("Grouped with:");
+        if let Action::ReplaceOutput = self.action {
+            let preset = self.selected.and_then(|selected| self.presets.get(selected));
 
-            for output_id in &settings.output_ids {
-                if output_id == primary_output_id {
-                    continue;
-                }
+            if let (Some(preset), Some(from), Some(to)) = (preset, &self.replace_from, &self.replace_to) {
+                if preset.output_ids.contains(from) && !can_replace_output(preset, from, to, outputs) {
+                    let err_msg = "The new output can't group with the other members".to_owned();
 
-                if let Some(sec_output) = outputs.get(output_id) {
-                    subtitle.push('\n');
-                    subtitle.push_str(&sec_output.display_name);
+                    errors.push(("replace_to".to_owned(), err_msg));
                 }
             }
+        }
 
-            widgets.push(Widget::Label(Label {
-                title: name.to_owned(),
-                subtitle: Some(subtitle)
-            }));
+        if let (Action::Edit, VolumeType::Preset) = (&self.action, &self.volume_type) {
+            let volume = self.volume_output_id
+                .as_ref()
+                .and_then(|output_id| outputs.get(output_id))
+                .and_then(|output| output.volume.as_ref());
+
+            if let Some(volume) = volume {
+                let err_msg = if self.volume_as_percent {
+                    match self.volume_level.trim().parse::<u32>() {
+                        Ok(percent) if percent <= 100 => None,
+                        _ => Some("Volume level should be a percentage between 0 and 100".to_owned())
+                    }
+                } else {
+                    check_volume_level(volume, &self.volume_level)
+                };
+
+                if let Some(err_msg) = err_msg {
+                    errors.push(("volume_level".to_owned(), err_msg));
+                }
+            }
         }
-    }
 
-    Layout {
-        settings,
-        widgets,
-        has_error
+        errors
     }
 }
 
-#[tokio::main(flavor = "current_thread")]
-async fn main() {
-    let mut roon = RoonApi::new(info!("com.theappgineer", "Zone Presets"));
-    let mut provided: HashMap<String, Svc> = HashMap::new();
-    let output_list = Arc::new(Mutex::new(HashMap::new()));
-    let last_selected = Arc::new(Mutex::new((None, None)));
-    let settings = serde_json::from_value::<GroupingSettings>(RoonApi::load_config("settings")).unwrap_or_default();
-    let saved_settings = Arc::new(Mutex::new(settings));
+// Fills in the placeholders of a user template, an empty template selects the default
+fn render_status(template: &str, default: &str, values: &[(&str, String)]) -> String {
+    let template = template.trim();
+    let mut status_msg = if template.is_empty() { default } else { template }.to_owned();
 
-    let output_list_clone = output_list.clone();
-    let last_selected_clone = last_selected.clone();
-    let saved_settings_clone = saved_settings.clone();
-    let get_settings_cb = move |cb: fn(Layout<GroupingSettings>) -> Vec<RespProps>| -> Vec<RespProps> {
-        let output_list = output_list_clone.lock().unwrap();
-        let mut last_selected = last_selected_clone.lock().unwrap();
-        let saved_settings = saved_settings_clone.lock().unwrap();
+    for (placeholder, value) in values {
+        status_msg = status_msg.replace(&format!("{{{}}}", placeholder), value);
+    }
 
-        *last_selected = (saved_settings.selected, saved_settings.volume_output_id.to_owned());
+    status_msg
+}
 
-        cb(make_layout(saved_settings.to_owned(), &output_list))
-    };
+fn unknown_placeholder<'a>(template: &'a str, placeholders: &[&str]) -> Option<&'a str> {
+    let mut rest = template;
 
-    let output_list_clone = output_list.clone();
-    let save_settings_cb = move |is_dry_run: bool, mut settings: GroupingSettings| -> Vec<RespProps> {
-        let output_list = output_list_clone.lock().unwrap();
-        let mut last_selected = last_selected.lock().unwrap();
-        let mut resp_props: Vec<RespProps> = Vec::new();
+    while let Some(start) = rest.find('{') {
+        let end = start + rest[start..].find('}')?;
+        let placeholder = &rest[start + 1..end];
 
-        if let Action::Delete = settings.action {
-            if let Some(index) = settings.selected {
-                if index < settings.presets.len() {
-                    settings.presets.remove(index);
-                    settings.selected = None;
-                }
-            }
+        if !placeholders.contains(&placeholder) {
+            return Some(placeholder)
         }
 
-        let selected_pair = (settings.selected, settings.volume_output_id.to_owned());
+        rest = &rest[end + 1..];
+    }
 
-        if selected_pair != *last_selected {
-            load_preset(&mut settings, &output_list);
+    None
+}
 
-            *last_selected = selected_pair;
-        } else {
-            store_preset(&mut settings);
-            store_volume(&mut settings, &output_list);
+fn is_verbose() -> bool {
+    std::env::var("VERBOSE").map_or(false, |verbose| !verbose.is_empty() && verbose != "0")
+}
+
+fn format_age(time: SystemTime) -> String {
+    let secs = time.elapsed().map_or(0, |elapsed| elapsed.as_secs());
+
+    match secs {
+        0..=9 => "just now".to_owned(),
+        10..=59 => format!("{}s ago", secs),
+        60..=3599 => format!("{}m ago", secs / 60),
+        3600..=86399 => format!("{}h ago", secs / 3600),
+        _ => format!("{}d ago", secs / 86400)
+    }
+}
+
+// Formats as YYYY-MM-DD (UTC), without pulling in a date crate
+fn format_date(time: SystemTime) -> String {
+    let days = time.duration_since(UNIX_EPOCH).map_or(0, |duration| duration.as_secs() / 86400) as i64;
+    // Civil from days, see http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+fn volume_unit(volume: &Volume) -> &'static str {
+    match volume.scale {
+        Scale::Decibel => "dB",
+        _ => ""
+    }
+}
+
+fn volume_step(volume: &Volume) -> f32 {
+    if volume.step > 0.0 {
+        volume.step
+    } else {
+        1.0
+    }
+}
+
+fn snap_to_step(volume: &Volume, level: f32) -> f32 {
+    let step = volume_step(volume);
+
+    (level / step).round() * step
+}
+
+fn api_level(level: f32) -> i32 {
+    level.round() as i32
+}
+
+// The API only takes whole values, a fractional level is set to the whole level below it
+// and then raised by the steps that remain
+fn volume_requests(level: f32, step: f32) -> Vec<(&'static str, i32)> {
+    let whole = level.floor();
+    let steps = if step > 0.0 { ((level - whole) / step).round() as i32 } else { 0 };
+
+    if steps == 0 {
+        vec![("absolute", api_level(level))]
+    } else {
+        vec![("absolute", api_level(whole)), ("relative_step", steps)]
+    }
+}
+
+fn live_volume_level(volume: &Volume) -> f32 {
+    // Snap to the step size of the output, dB outputs report fractional negative values
+    snap_to_step(volume, volume.value)
+}
+
+fn percent_to_level(volume: &Volume, percent: f32) -> f32 {
+    let range = volume.hard_limit_max - volume.hard_limit_min;
+
+    snap_to_step(volume, volume.hard_limit_min + range * percent / 100.0)
+}
+
+fn level_to_percent(volume: &Volume, level: f32) -> f32 {
+    let range = volume.hard_limit_max - volume.hard_limit_min;
+
+    if range > 0.0 {
+        ((level - volume.hard_limit_min) / range * 100.0).round()
+    } else {
+        0.0
+    }
+}
+
+fn check_volume_level(volume: &Volume, volume_level: &str) -> Option<String> {
+    let step = volume_step(volume);
+    let unit = volume_unit(volume);
+
+    match volume_level.trim().parse::<f32>() {
+        Ok(level) if level < volume.hard_limit_min || level > volume.hard_limit_max => Some(format!(
+            "Volume level should be between {}{} and {}{}",
+            volume.hard_limit_min,
+            unit,
+            volume.hard_limit_max,
+            unit
+        )),
+        Ok(level) => {
+            let steps = (level - volume.hard_limit_min) / step;
+
+            if (steps - steps.round()).abs() > 0.001 {
+                Some(format!("Volume level should be a multiple of {}{}", step, unit))
+            } else {
+                None
+            }
         }
+        Err(_) => Some("Volume level should be a number".to_owned())
+    }
+}
 
-        let layout = make_layout(settings, &output_list);
-        let layout = layout.serialize(serde_json::value::Serializer).unwrap();
+fn store_preset(settings: &mut GroupingSettings, outputs: &HashMap<String, Output>) -> Result<(), StoreError> {
+    let name = settings.name.to_owned();
 
-        send_complete!(resp_props, "Success", Some(json!({"settings": layout})));
+    if settings.selection() == PresetSelection::None {
+        return Ok(())
+    }
 
-        if !is_dry_run && !layout["has_error"].as_bool().unwrap() {
-            send_continue_all!(resp_props, "subscribe_settings", "Changed", Some(json!({"settings": layout})));
+    // A blank name would only leave a ghost entry that the preset list doesn't show
+    if name.trim().is_empty() {
+        return Err(StoreError::NoName)
+    }
+
+    let primary_output_id = match settings.primary_output_id.to_owned() {
+        Some(primary_output_id) => primary_output_id,
+        None if settings.selection() == PresetSelection::New => return Err(StoreError::NoPrimaryOutput),
+        // None of the outputs of the preset is online to lead it
+        None => return Ok(())
+    };
+    let mut output_ids = settings.output_ids.to_owned();
+
+    if output_ids.len() == 0 {
+        output_ids.push(primary_output_id.to_owned());
+        settings.output_ids.push(primary_output_id.to_owned());
+    }
+
+    // Only adding a member needs a pick, the other fields of an existing preset are stored regardless
+    match settings.add.to_owned() {
+        Some(add) => {
+            if !output_ids.contains(&add) {
+                output_ids.push(add.to_owned());
+                settings.output_ids.push(add);
+            }
         }
+        None if settings.selection() == PresetSelection::New => return Err(StoreError::NoOutputToAdd),
+        None => ()
+    }
 
-        resp_props
+    // Keep the first occurrence of each output, with the primary output first
+    let mut unique_ids = vec![primary_output_id];
+
+    for output_id in output_ids {
+        if !unique_ids.contains(&output_id) {
+            unique_ids.push(output_id);
+        }
+    }
+
+    let output_ids = unique_ids;
+
+    settings.output_ids = output_ids.to_owned();
+
+    let volume_master = settings.volume_master
+        .to_owned()
+        .filter(|volume_master| output_ids.contains(volume_master));
+    let stagger_ms = settings.stagger_ms.parse::<u32>().ok().filter(|stagger_ms| *stagger_ms > 0);
+    let volume_delay_ms = settings.volume_delay_ms.parse::<u32>().ok().filter(|volume_delay_ms| *volume_delay_ms > 0);
+    let wait_for_outputs_ms = settings.wait_for_outputs_ms.parse::<u32>().ok().filter(|wait_ms| *wait_ms > 0);
+    let fade_ms = settings.fade_ms.parse::<u32>().ok().filter(|fade_ms| *fade_ms > 0);
+    let cooldown_seconds = settings.cooldown_seconds.parse::<u32>().ok().filter(|cooldown_seconds| *cooldown_seconds > 0);
+    let master_scale = settings.master_scale
+        .parse::<u32>()
+        .ok()
+        .filter(|percent| *percent != 100)
+        .map(|percent| percent as f32 / 100.0);
+    let existing = match settings.selection() {
+        PresetSelection::Existing(selected) => settings.presets.get(selected),
+        _ => None
     };
-    let (svc, settings) = Settings::new(&roon, Box::new(get_settings_cb), Box::new(save_settings_cb));
+    // Keep the saved name of offline outputs, it is needed to resolve re-added devices
+    let output_names = output_ids
+        .iter()
+        .filter_map(|output_id| {
+            let name = outputs.get(output_id)
+                .map(|output| output.display_name.to_owned())
+                .or_else(|| existing?.output_names.get(output_id).cloned())?;
 
-    provided.insert(settings::SVCNAME.to_owned(), svc);
+            Some((output_id.to_owned(), name))
+        })
+        .collect();
+    let volume_profiles = existing.map(|preset| preset.volume_profiles.to_owned()).unwrap_or_default();
+    // Carry over the levels of remaining members, otherwise store_volume reseeds them from the live level
+    let volumes = existing
+        .map(|preset| {
+            preset.volumes
+                .iter()
+                .filter(|(output_id, _)| output_ids.contains(*output_id))
+                .map(|(output_id, volume)| (output_id.to_owned(), *volume))
+                .collect()
+        })
+        .unwrap_or_default();
+    let volume_percents = existing
+        .map(|preset| {
+            preset.volume_percents
+                .iter()
+                .filter(|(output_id, _)| output_ids.contains(*output_id))
+                .map(|(output_id, percent)| (output_id.to_owned(), *percent))
+                .collect()
+        })
+        .unwrap_or_default();
+    let active_profile = settings.active_profile
+        .to_owned()
+        .filter(|profile| volume_profiles.contains_key(profile));
+    let preset = Preset {
+        name,
+        output_ids,
+        output_names,
+        play_item: Some(settings.play_item.trim().to_owned()).filter(|play_item| !play_item.is_empty()),
+        room_set: settings.room_set.to_owned(),
+        priority: settings.priority.trim().parse().unwrap_or_default(),
+        zone_label: Some(settings.zone_label.trim().to_owned()).filter(|zone_label| !zone_label.is_empty()),
+        volumes,
+        volume_percents,
+        volume_profiles,
+        active_profile,
+        volume_master,
+        stagger_ms,
+        volume_delay_ms,
+        wait_for_outputs_ms,
+        fade_ms,
+        cooldown_seconds,
+        master_scale,
+        // Set here as well, store_volume may bail out before reaching it
+        volume_type: settings.volume_type.to_owned(),
+        volume_as_percent: settings.volume_as_percent,
+        exclusive: settings.exclusive,
+        power_on_members: settings.power_on_members,
+        mirror: settings.mirror,
+        apply_volume_on_match: settings.apply_volume_on_match,
+        activation_count: existing.map_or(0, |preset| preset.activation_count),
+        last_activated_at: existing.and_then(|preset| preset.last_activated_at.to_owned()),
+        armed: existing.and_then(|preset| preset.armed),
+        favorite: existing.map_or(false, |preset| preset.favorite),
+        ..Default::default()
+    };
 
-    let (svc, status) = Status::new(&roon);
+    match settings.selection() {
+        PresetSelection::Existing(selected) => {
+            settings.presets[selected] = preset;
+        }
+        PresetSelection::New => {
+            settings.selected = Some(settings.presets.len());
+            settings.presets.push(preset);
+        }
+        PresetSelection::None => ()
+    }
 
-    provided.insert(status::SVCNAME.to_owned(), svc);
+    Ok(())
+}
 
-    let services = vec![
-        Services::Settings(settings),
-        Services::Status(status),
-        Services::Transport(Transport::new())
-    ];
-    let (mut handles, mut core_rx) = roon.start_discovery(provided, Some(services)).await.unwrap();
+fn store_volume(settings: &mut GroupingSettings, outputs: &HashMap<String, Output>) -> Result<(), StoreError> {
+    settings.volume_output_cleared = false;
 
-    let core_handler = async move {
-        let mut status = None;
-        let mut transport = None;
-        let mut matched_zone_id = None;
+    // A selection from before a membership change can't receive a volume
+    if let Some(volume_output_id) = &settings.volume_output_id {
+        if !settings.output_ids.contains(volume_output_id) {
+            settings.volume_output_id = None;
+            settings.volume_output_cleared = true;
+        }
+    }
 
-        loop {
-            if let Some((core, msg)) = core_rx.recv().await {
-                match core {
-                    CoreEvent::Found(mut core) => {
-                        println!("Core found: {}, version {}", core.display_name, core.display_version);
+    // Nothing to store without a preset, a volume output or volumes of the preset itself
+    let preset = match settings.selected.and_then(|selected| settings.presets.get_mut(selected)) {
+        Some(preset) => preset,
+        None => return Ok(())
+    };
+
+    preset.volume_type = settings.volume_type.to_owned();
+    preset.volume_as_percent = settings.volume_as_percent;
 
-                        status = core.get_status().cloned();
+    if let VolumeType::Preset = settings.volume_type {
+        let volume_output_id = match settings.volume_output_id.as_ref() {
+            Some(volume_output_id) => volume_output_id,
+            None => return Ok(())
+        };
+        let volume = outputs.get(volume_output_id).and_then(|output| output.volume.as_ref());
 
-                        if let Some(status) = status.as_ref() {
-                            status.set_status("No preset active".to_owned(), false).await;
-                        };
+        if let None = preset.active_volumes().get(volume_output_id) {
+            let volume = volume.ok_or(StoreError::OutputUnavailable)?;
+            let volume_level = live_volume_level(volume);
 
-                        transport = core.get_transport().cloned();
+            settings.volume_level = if settings.volume_as_percent {
+                level_to_percent(volume, volume_level).to_string()
+            } else {
+                volume_level.to_string()
+            };
+        }
 
-                        if let Some(transport) = transport.as_ref() {
-                            transport.subscribe_zones().await;
-                            transport.subscribe_outputs().await;
-                        }
-                    }
-                    CoreEvent::Lost(core) => {
-                        println!("Core lost: {}, version {}", core.display_name, core.display_version);
-                    }
-                    _ => ()
+        if let Ok(volume_level) = settings.volume_level.trim().parse::<f32>() {
+            if settings.volume_as_percent {
+                let volume = volume.ok_or(StoreError::OutputUnavailable)?;
+
+                // Keep the percentage for display, activation uses the native level
+                if !preset.is_profile_active() {
+                    preset.volume_percents.insert(volume_output_id.to_owned(), volume_level);
                 }
 
-                if let Some((_, parsed)) = msg {
-                    match parsed {
-                        Parsed::Zones(zones) => {
-                            if matched_zone_id.is_none() {
-                                let mut presets = saved_settings.lock().unwrap().presets.to_owned();
-
-                                if let Some((matching_preset, zone)) = match_preset(&mut presets, &zones) {
-                                    let status_msg = format!(
-                                        "Grouped zone \"{}\" represents the \"{}\" preset", 
-                                        zone.display_name,
-                                        matching_preset.name
-                                    );
+                preset.active_volumes_mut().insert(volume_output_id.to_owned(), percent_to_level(volume, volume_level));
+            } else {
+                if !preset.is_profile_active() {
+                    preset.volume_percents.remove(volume_output_id);
+                }
 
-                                    matched_zone_id = Some(zone.zone_id.to_owned());
+                preset.active_volumes_mut().insert(volume_output_id.to_owned(), volume.map_or(volume_level, |volume| snap_to_step(volume, volume_level)));
+            }
 
-                                    if let Some(status) = status.as_ref() {
-                                        status.set_status(status_msg, false).await;
-                                    }
-                                }
-                            }
+            Ok(())
+        } else {
+            Err(StoreError::InvalidVolumeLevel)
+        }
+    } else {
+        Ok(())
+    }
+}
 
-                            let mut settings = saved_settings.lock().unwrap();
+fn store_profile(settings: &mut GroupingSettings) -> Option<()> {
+    let profile = settings.new_profile.trim().to_owned();
 
-                            settings.extracted_preset = extract_preset(&zones);
-                        }
-                        Parsed::ZonesRemoved(removed_zone_ids) => {
-                            if let Some(zone_id) = &matched_zone_id {
-                                if removed_zone_ids.contains(zone_id) {
-                                    matched_zone_id = None;
+    if profile.is_empty() {
+        return None
+    }
 
-                                    if let Some(status) = status.as_ref() {
-                                        status.set_status("No preset active".to_owned(), false).await;
-                                    }
-                                }
-                            }
-                        }
-                        Parsed::Outputs(outputs) => {
-                            for output in outputs {
-                                let output_id = output.output_id.to_owned();
-                                let mut output_list = output_list.lock().unwrap();
+    let preset = settings.presets.get_mut(settings.selected?)?;
 
-                                output_list.insert(output_id, output);
-                            }
-                        }
-                        Parsed::OutputsRemoved(output_ids) => {
-                            let mut output_list = output_list.lock().unwrap();
+    preset.volume_profiles.insert(profile.to_owned(), preset.volumes.to_owned());
+    preset.active_profile = Some(profile.to_owned());
+    settings.active_profile = Some(profile);
+    settings.new_profile = String::new();
 
-                            for output_id in output_ids {
-                                output_list.remove(&output_id);
-                            }
-                        }
-                        Parsed::SettingsSaved(settings) => {
-                            let mut nv_settings = settings.to_owned();
+    Some(())
+}
 
-                            nv_settings["extracted_preset"] = serde_json::Value::Null;
+fn remove_profile(settings: &mut GroupingSettings) -> Option<()> {
+    let profile = settings.active_profile.take()?;
+    let preset = settings.presets.get_mut(settings.selected?)?;
 
-                            if let Ok(mut settings) = serde_json::from_value::<GroupingSettings>(settings) {
-                                let mut status_msg = "Settings saved".to_owned();
+    preset.volume_profiles.remove(&profile);
+    preset.active_profile = None;
+    settings.action = Action::Edit;
 
-                                if settings.selected.is_some() && settings.primary_output_id.is_some() {
-                                    if let Some(transport) = transport.as_ref() {
-                                        let output_ids: Vec<&str> = settings.output_ids
-                                            .iter()
-                                            .filter_map(|output_id| {
-                                                if output_list.lock().unwrap().contains_key(output_id) {
-                                                    Some(output_id.as_str())
-                                                } else {
-                                                    None
-                                                }
-                                            })
-                                            .collect();
+    Some(())
+}
 
-                                        match settings.action {
-                                            Action::Activate => {
-                                                // Deactivate any active grouping
-                                                if let Some(extracted_preset) = &settings.extracted_preset {
-                                                    let output_ids = extracted_preset.output_ids
-                                                        .iter()
-                                                        .map(|output_id| output_id.as_str())
-                                                        .collect();
-                                                    transport.ungroup_outputs(output_ids).await;
-                                                }
+fn store_batch(settings: &mut GroupingSettings) {
+    if let Some(add) = settings.batch_add.take() {
+        if add < settings.presets.len() && !settings.batch.contains(&add) {
+            settings.batch.push(add);
+        }
+    }
 
-                                                let selected = settings.selected.unwrap();
+    if let Some(remove) = settings.batch_remove.take() {
+        settings.batch.retain(|index| *index != remove);
+    }
 
-                                                if let Some(preset) = settings.presets.get(selected) {
-                                                    match preset.volume_type {
-                                                        VolumeType::Untouched => (),
-                                                        _ => {
-                                                            for (output_id, value) in &preset.volumes {
-                                                                if output_ids.contains(&output_id.as_str()) {
-                                                                    transport.change_volume(output_id, "absolute", *value).await;
-                                                                }
-                                                            }
-                                                        }
-                                                    }
-                                                }
+    let count = settings.presets.len();
 
-                                                transport.group_outputs(output_ids).await;
-                                                status_msg = format!("Preset \"{}\" activated", settings.name);
-                                            }
-                                            Action::Deactivate => {
-                                                let selected = settings.selected.unwrap();
+    settings.batch.retain(|index| *index < count);
+}
 
-                                                if let Some(preset) = settings.presets.get_mut(selected) {
-                                                    if let VolumeType::LastUsed = preset.volume_type {
-                                                        let output_list = output_list.lock().unwrap();
-                                                        let volumes = &mut nv_settings["presets"].get_mut(selected).unwrap()["volumes"];
+// The batch in activation order, higher priority first and otherwise in the order they were added
+fn batch_order(presets: &[Preset], batch: &[usize]) -> Vec<usize> {
+    let mut order: Vec<usize> = batch.iter().copied().filter(|index| *index < presets.len()).collect();
 
-                                                        for output_id in &output_ids {
-                                                            if let Some(output) = output_list.get(*output_id) {
-                                                                if let Some(volume) = output.volume.as_ref() {
-                                                                    let volume_level = volume.value as i32;
+    order.sort_by(|a, b| presets[*b].priority.cmp(&presets[*a].priority));
+    order
+}
 
-                                                                    preset.volumes.insert((*output_id).to_string(), volume_level);
-                                                                    volumes[*output_id] = volume_level.into();
-                                                                }
-                                                            }
-                                                        }
-                                                    }
-                                                }
+fn store_alias(settings: &mut GroupingSettings) {
+    let alias = settings.alias_name.trim().to_owned();
 
-                                                transport.ungroup_outputs(output_ids).await;
-                                                status_msg = format!("Preset \"{}\" deactivated", settings.name);
-                                            }
-                                            Action::Edit => {
-                                                transport.get_zones().await;
-                                            }
-                                            _ => ()
-                                        }
-                                    }
-                                }
+    if !alias.is_empty() {
+        if let Some(output_id) = settings.alias_output_id.take() {
+            settings.output_aliases.insert(output_id, alias);
+            settings.alias_name = String::new();
+        }
+    }
 
-                                if let Action::Delete = settings.action {
-                                    matched_zone_id = None;
-                                    status_msg = format!("Preset \"{}\" deleted", settings.name);
-                                }
+    if let Some(output_id) = settings.alias_remove.take() {
+        settings.output_aliases.remove(&output_id);
+    }
+}
 
-                                if let Some(status) = status.as_ref() {
-                                    status.set_status(status_msg, false).await;
-                                }
+fn make_aliases_group(settings: &GroupingSettings, outputs: &HashMap<String, Output>) -> Widget {
+    let mut values = vec![HashMap::from([ ("title", "(select output)".into()), ("value", Value::Null) ])];
+    let mut remove_values = values.to_owned();
+    let mut aliases = Vec::new();
 
-                                let mut saved_settings = saved_settings.lock().unwrap();
+    for (output_id, output) in outputs {
+        values.push(HashMap::from([ ("title", output.display_name.to_owned().into()), ("value", output_id.to_owned().into()) ]));
 
-                                if *saved_settings.name != settings.name {
-                                    // A name change requires new matching
-                                    matched_zone_id = None;
-                                }
+        if let Some(alias) = settings.output_aliases.get(output_id) {
+            remove_values.push(HashMap::from([ ("title", alias.to_owned().into()), ("value", output_id.to_owned().into()) ]));
+            aliases.push(format!("{}: {}", output.display_name, alias));
+        }
+    }
 
-                                *saved_settings = settings;
-                            }
+    aliases.sort();
 
-                            RoonApi::save_config("settings", nv_settings).unwrap();
-                        }
-                        _ => ()
+    let mut items = vec![
+        Widget::Dropdown(Dropdown {
+            title: "Output",
+            subtitle: None,
+            values,
+            setting: "alias_output_id"
+        }),
+        Widget::Textbox(Textbox {
+            title: "Alias",
+            subtitle: Some("Name to show for the output within this extension".to_owned()),
+            setting: "alias_name"
+        })
+    ];
+
+    if !aliases.is_empty() {
+        items.push(Widget::Dropdown(Dropdown {
+            title: "Remove Alias",
+            subtitle: None,
+            values: remove_values,
+            setting: "alias_remove"
+        }));
+        items.push(Widget::Label(Label {
+            title: "Aliases".to_owned(),
+            subtitle: Some(aliases.join("\n"))
+        }));
+    }
+
+    Widget::Group(Group {
+        title: "Output Aliases",
+        subtitle: None,
+        collapsable: true,
+        items
+    })
+}
+
+fn store_room_set(settings: &mut GroupingSettings) -> Option<()> {
+    let selected = settings.room_set_selected?;
+
+    if selected >= settings.room_sets.len() {
+        let name = settings.room_set_name.trim().to_owned();
+
+        if name.is_empty() || settings.room_sets.iter().any(|room_set| room_set.name == name) {
+            return None
+        }
+
+        settings.room_sets.push(RoomSet { name, output_ids: Vec::new() });
+        settings.room_set_selected = Some(settings.room_sets.len() - 1);
+        settings.room_set_name = String::new();
+    }
+
+    let selected = settings.room_set_selected?;
+    let room_set = settings.room_sets.get_mut(selected)?;
+
+    if let Some(add) = settings.room_set_add.take() {
+        if !room_set.output_ids.contains(&add) {
+            room_set.output_ids.push(add);
+        }
+    }
+
+    if let Some(remove) = settings.room_set_remove.take() {
+        room_set.output_ids.retain(|output_id| *output_id != remove);
+
+        // A room set without outputs is deleted, presets referring to it keep their own outputs
+        if room_set.output_ids.is_empty() {
+            settings.room_sets.remove(selected);
+            settings.room_set_selected = None;
+        }
+    }
+
+    Some(())
+}
+
+// Presets with the members of their room set added to their own outputs
+fn resolve_room_sets(presets: &[Preset], room_sets: &[RoomSet]) -> Vec<Preset> {
+    presets
+        .iter()
+        .map(|preset| {
+            let mut preset = preset.to_owned();
+            let room_set = preset.room_set
+                .as_ref()
+                .and_then(|name| room_sets.iter().find(|room_set| room_set.name == *name));
+
+            if let Some(room_set) = room_set {
+                for output_id in &room_set.output_ids {
+                    if !preset.output_ids.contains(output_id) {
+                        preset.output_ids.push(output_id.to_owned());
                     }
                 }
             }
+
+            preset
+        })
+        .collect()
+}
+
+fn make_room_sets_group(settings: &GroupingSettings, outputs: &HashMap<String, Output>) -> Widget {
+    let mut values = vec![HashMap::from([ ("title", "(select room set)".into()), ("value", Value::Null) ])];
+
+    for (index, room_set) in settings.room_sets.iter().enumerate() {
+        values.push(HashMap::from([ ("title", room_set.name.to_owned().into()), ("value", index.into()) ]));
+    }
+
+    values.push(HashMap::from([ ("title", "New Room Set".into()), ("value", settings.room_sets.len().into()) ]));
+
+    let mut items = vec![Widget::Dropdown(Dropdown {
+        title: "Room Set",
+        subtitle: None,
+        values,
+        setting: "room_set_selected"
+    })];
+
+    match settings.room_set_selected.map(|selected| settings.room_sets.get(selected)) {
+        Some(Some(room_set)) => {
+            let mut add_values = vec![HashMap::from([ ("title", "(select output)".into()), ("value", Value::Null) ])];
+            let mut remove_values = add_values.to_owned();
+
+            for (output_id, output) in outputs {
+                let value = HashMap::from([ ("title", settings.output_name(output).to_owned().into()), ("value", output_id.to_owned().into()) ]);
+
+                if room_set.output_ids.contains(output_id) {
+                    remove_values.push(value);
+                } else {
+                    add_values.push(value);
+                }
+            }
+
+            items.push(Widget::Dropdown(Dropdown {
+                title: "Add Output",
+                subtitle: None,
+                values: add_values,
+                setting: "room_set_add"
+            }));
+            items.push(Widget::Dropdown(Dropdown {
+                title: "Remove Output",
+                subtitle: Some("Removing the last output deletes the room set".to_owned()),
+                values: remove_values,
+                setting: "room_set_remove"
+            }));
         }
-    };
+        Some(None) => {
+            items.push(Widget::Textbox(Textbox {
+                title: "Room Set Name",
+                subtitle: None,
+                setting: "room_set_name"
+            }));
+        }
+        None => ()
+    }
 
-    handles.push(tokio::spawn(core_handler));
+    Widget::Group(Group {
+        title: "Room Sets",
+        subtitle: Some("Sets of outputs shared by several presets".to_owned()),
+        collapsable: true,
+        items
+    })
+}
 
-    for handle in handles {
-        handle.await.unwrap();
+fn aliased_name<'a>(aliases: &'a HashMap<String, String>, output_id: &str, name: &'a str) -> &'a str {
+    aliases.get(output_id).map_or(name, |alias| alias.as_str())
+}
+
+fn make_auto_name(output_ids: &[String], outputs: &HashMap<String, Output>) -> String {
+    output_ids
+        .iter()
+        .filter_map(|output_id| outputs.get(output_id))
+        .map(|output| output.display_name.as_str())
+        .collect::<Vec<_>>()
+        .join(" + ")
+}
+
+fn apply_auto_name(settings: &mut GroupingSettings, outputs: &HashMap<String, Output>) {
+    if !settings.auto_name {
+        return
+    }
+
+    let mut output_ids = settings.output_ids.to_owned();
+
+    if output_ids.is_empty() {
+        output_ids.extend(settings.primary_output_id.to_owned());
+    }
+
+    if let Some(add) = settings.add.as_ref() {
+        if !output_ids.contains(add) {
+            output_ids.push(add.to_owned());
+        }
+    }
+
+    let previous_name = make_auto_name(&settings.output_ids, outputs);
+
+    if settings.name.is_empty() || settings.name == previous_name {
+        settings.name = make_auto_name(&output_ids, outputs);
+    } else {
+        // A custom name was entered in the Name textbox
+        settings.auto_name = false;
+    }
+}
+
+fn copy_volume(settings: &mut GroupingSettings, outputs: &HashMap<String, Output>) -> Option<()> {
+    settings.copy_clamped.clear();
+
+    let selected = settings.selected?;
+    let preset = settings.presets.get_mut(selected)?;
+    let volume_output_id = settings.volume_output_id.as_ref()?;
+    let volume_level = *preset.volumes.get(volume_output_id)?;
+
+    for output_id in &preset.output_ids {
+        if let Some(output) = outputs.get(output_id) {
+            let volume = match output.volume.as_ref() {
+                Some(volume) => volume,
+                None => continue
+            };
+            let clamped_level = volume_level.clamp(volume.hard_limit_min, volume.hard_limit_max);
+
+            // Reported in the status once the settings are saved
+            if clamped_level != volume_level {
+                let name = aliased_name(&settings.output_aliases, output_id, &output.display_name);
+
+                settings.copy_clamped.push(name.to_owned());
+            }
+
+            preset.volumes.insert(output_id.to_owned(), clamped_level);
+            preset.volume_percents.remove(output_id);
+        }
+    }
+
+    Some(())
+}
+
+fn reset_volumes(settings: &mut GroupingSettings) -> Option<()> {
+    let selected = settings.selected?;
+    let preset = settings.presets.get_mut(selected)?;
+
+    // Membership stays, only the volume configuration goes
+    preset.volume_type = VolumeType::Untouched;
+    preset.volumes.clear();
+    preset.volume_percents.clear();
+    preset.volume_profiles.clear();
+    preset.active_profile = None;
+
+    settings.volume_type = VolumeType::Untouched;
+    settings.active_profile = None;
+
+    Some(())
+}
+
+fn set_armed(settings: &mut GroupingSettings, is_armed: bool) -> Option<()> {
+    let preset = settings.presets.get_mut(settings.selected?)?;
+
+    preset.armed = Some(is_armed);
+
+    Some(())
+}
+
+fn set_favorite(settings: &mut GroupingSettings, is_favorite: bool) -> Option<()> {
+    let preset = settings.presets.get_mut(settings.selected?)?;
+
+    preset.favorite = is_favorite;
+
+    Some(())
+}
+
+fn can_replace_output(preset: &Preset, from: &str, to: &str, outputs: &HashMap<String, Output>) -> bool {
+    let to_output = match outputs.get(to) {
+        Some(output) => output,
+        None => return false
+    };
+
+    // Offline members can't be checked, they are left to the grouping at activation
+    !preset.output_ids.iter().any(|output_id| output_id == to) && preset.output_ids
+        .iter()
+        .filter(|output_id| *output_id != from && outputs.contains_key(*output_id))
+        .all(|output_id| to_output.can_group_with_output_ids.contains(output_id))
+}
+
+fn replace_output(settings: &mut GroupingSettings, outputs: &HashMap<String, Output>) -> Option<()> {
+    let selected = settings.selected?;
+    let from = settings.replace_from.to_owned()?;
+    let to = settings.replace_to.to_owned()?;
+    let preset = settings.presets.get_mut(selected)?;
+
+    if !preset.output_ids.contains(&from) || !can_replace_output(preset, &from, &to, outputs) {
+        return None
+    }
+
+    preset.replace_output_id(&from, &to);
+
+    if let Some(output) = outputs.get(&to) {
+        preset.output_names.insert(to.to_owned(), output.display_name.to_owned());
+    }
+
+    settings.replace_output_id(&from, &to);
+
+    Some(())
+}
+
+// Roon lists an output as groupable with itself, that alone doesn't make a group
+fn is_groupable(output: &Output) -> bool {
+    output.can_group_with_output_ids.iter().any(|output_id| *output_id != output.output_id)
+}
+
+fn find_groupable_output(outputs: &HashMap<String, Output>) -> Option<String> {
+    // Sort by name to offer the same output each time
+    let mut groupable: Vec<&Output> = outputs
+        .values()
+        .filter(|output| is_groupable(output))
+        .collect();
+
+    groupable.sort_by(|a, b| a.display_name.cmp(&b.display_name));
+    groupable.first().map(|output| output.output_id.to_owned())
+}
+
+// Presets may come from another system, bring their levels within the range of the outputs present here
+fn fit_preset_volumes(presets: &mut [Preset], outputs: &HashMap<String, Output>) -> usize {
+    let mut adjusted = 0;
+
+    for preset in presets.iter_mut() {
+        let mut clamped_ids = Vec::new();
+        let levels = preset.volumes
+            .iter_mut()
+            .map(|(output_id, level)| (true, output_id, level))
+            .chain(preset.volume_profiles
+                .values_mut()
+                .flat_map(|volumes| volumes.iter_mut().map(|(output_id, level)| (false, output_id, level))));
+
+        for (is_volume, output_id, level) in levels {
+            let volume = outputs
+                .get(output_id)
+                .and_then(|output| output.volume.as_ref())
+                .filter(|volume| !matches!(volume.scale, Scale::Incremental));
+
+            if let Some(volume) = volume {
+                let clamped = level.clamp(volume.hard_limit_min, volume.hard_limit_max);
+
+                if clamped != *level {
+                    println!("Preset \"{}\": adjusted stored volume of {} from {} to {}", preset.name, output_id, level, clamped);
+
+                    *level = clamped;
+                    adjusted += 1;
+
+                    if is_volume {
+                        clamped_ids.push(output_id.to_owned());
+                    }
+                }
+            }
+        }
+
+        // The percent of a clamped level no longer matches what was stored with it
+        for output_id in clamped_ids {
+            let volume = outputs.get(&output_id).and_then(|output| output.volume.as_ref());
+
+            if let (Some(volume), Some(level), Some(percent)) = (volume, preset.volumes.get(&output_id), preset.volume_percents.get_mut(&output_id)) {
+                *percent = level_to_percent(volume, *level);
+            }
+        }
+    }
+
+    adjusted
+}
+
+// Resolves member ids that are gone to a current output with the same saved name
+fn resolve_by_name(preset: &mut Preset, outputs: &HashMap<String, Output>) -> Vec<(String, String)> {
+    let mut resolved = Vec::new();
+
+    for output_id in preset.output_ids.iter().filter(|output_id| !outputs.contains_key(*output_id)) {
+        if let Some(name) = preset.output_names.get(output_id) {
+            let candidates: Vec<&Output> = outputs
+                .values()
+                .filter(|output| output.display_name == *name)
+                .collect();
+
+            // Colliding names can't be resolved reliably
+            if let [output] = candidates.as_slice() {
+                if !preset.output_ids.contains(&output.output_id) {
+                    resolved.push((output_id.to_owned(), output.output_id.to_owned()));
+                }
+            }
+        }
+    }
+
+    for (old_id, new_id) in &resolved {
+        preset.replace_output_id(old_id, new_id);
+    }
+
+    resolved
+}
+
+fn reset_editor(settings: &mut GroupingSettings) {
+    settings.volume_type = VolumeType::Untouched;
+    settings.volume_master = None;
+    settings.stagger_ms = String::new();
+    settings.wait_for_outputs_ms = String::new();
+    settings.fade_ms = String::new();
+    settings.cooldown_seconds = String::new();
+    settings.volume_delay_ms = String::new();
+    settings.master_scale = String::new();
+    settings.volume_as_percent = false;
+    settings.exclusive = false;
+    settings.power_on_members = false;
+    settings.mirror = false;
+    settings.apply_volume_on_match = false;
+    settings.active_profile = None;
+    settings.new_profile = String::new();
+    settings.play_item = String::new();
+    settings.room_set = None;
+    settings.priority = String::new();
+    settings.zone_label = String::new();
+}
+
+fn load_preset(settings: &mut GroupingSettings, outputs: &HashMap<String, Output>) {
+    if let Some(selected) = settings.selected {
+        settings.auto_name = false;
+
+        if let Some(preset) = settings.presets.get(selected).filter(|preset| preset.output_ids.is_empty()) {
+            // Nothing to load, start over as if this was a new preset but keep the chosen action
+            settings.name = preset.name.to_owned();
+            settings.primary_output_id = find_groupable_output(outputs);
+            settings.output_ids = Vec::new();
+            settings.add = None;
+            reset_editor(settings);
+        } else if let Some(preset) = settings.presets.get_mut(selected) {
+            settings.name = preset.name.to_owned();
+            settings.output_ids = preset.output_ids.to_owned();
+
+            // Promote the first available member if the primary output is gone
+            if let Some(index) = settings.output_ids.iter().position(|output_id| outputs.contains_key(output_id)) {
+                let primary_output_id = settings.output_ids.remove(index);
+
+                settings.output_ids.insert(0, primary_output_id.to_owned());
+                settings.primary_output_id = Some(primary_output_id);
+            } else {
+                settings.primary_output_id = None;
+            }
+
+            settings.add = None;
+            settings.volume_type = preset.volume_type.to_owned();
+            settings.volume_master = preset.volume_master.to_owned();
+            settings.stagger_ms = preset.stagger_ms.map(|stagger_ms| stagger_ms.to_string()).unwrap_or_default();
+            settings.wait_for_outputs_ms = preset.wait_for_outputs_ms.map(|wait_ms| wait_ms.to_string()).unwrap_or_default();
+            settings.fade_ms = preset.fade_ms.map(|fade_ms| fade_ms.to_string()).unwrap_or_default();
+            settings.cooldown_seconds = preset.cooldown_seconds.map(|cooldown_seconds| cooldown_seconds.to_string()).unwrap_or_default();
+            settings.volume_delay_ms = preset.volume_delay_ms.map(|volume_delay_ms| volume_delay_ms.to_string()).unwrap_or_default();
+            settings.master_scale = preset.master_scale.map(|scale| ((scale * 100.0).round() as u32).to_string()).unwrap_or_default();
+            settings.volume_as_percent = preset.volume_as_percent;
+            settings.exclusive = preset.exclusive;
+            settings.power_on_members = preset.power_on_members;
+            settings.mirror = preset.mirror;
+            settings.apply_volume_on_match = preset.apply_volume_on_match;
+            settings.active_profile = preset.active_profile.to_owned();
+            settings.new_profile = String::new();
+            settings.play_item = preset.play_item.to_owned().unwrap_or_default();
+            settings.room_set = preset.room_set.to_owned();
+            settings.priority = if preset.priority != 0 { preset.priority.to_string() } else { String::new() };
+            settings.zone_label = preset.zone_label.to_owned().unwrap_or_default();
+            load_volume_level(settings, outputs);
+        } else if let Some(preset) = settings.extracted_preset.as_ref() {
+            settings.name = preset.name.to_owned();
+            settings.primary_output_id = preset.output_ids.first().cloned();
+            settings.output_ids = preset.output_ids.to_owned();
+            settings.action = Action::Edit;
+            settings.add = settings.output_ids.get(0).cloned();
+            reset_editor(settings);
+        } else {
+            settings.name = String::new();
+            settings.primary_output_id = find_groupable_output(outputs);
+            settings.output_ids = Vec::new();
+            settings.action = Action::Edit;
+            settings.add = None;
+            reset_editor(settings);
+        }
+    }
+}
+
+// Shows the level of the volume output, from the selected profile if there is one
+fn load_volume_level(settings: &mut GroupingSettings, outputs: &HashMap<String, Output>) {
+    let preset = match settings.selected.and_then(|selected| settings.presets.get_mut(selected)) {
+        Some(preset) => preset,
+        None => return
+    };
+
+    if let VolumeType::Preset = settings.volume_type {
+        if let Some(volume_output_id) = &settings.volume_output_id {
+            let volume = outputs.get(volume_output_id).and_then(|output| output.volume.as_ref());
+
+            if let Some(volume_level) = preset.active_volumes().get(volume_output_id).cloned() {
+                let percent = preset.volume_percents
+                    .get(volume_output_id)
+                    .cloned()
+                    .filter(|_| !preset.is_profile_active())
+                    .or_else(|| Some(level_to_percent(volume?, volume_level)));
+
+                settings.volume_level = match percent {
+                    Some(percent) if settings.volume_as_percent => percent.to_string(),
+                    _ => volume_level.to_string()
+                };
+            } else if let Some(volume) = volume {
+                let volume_level = live_volume_level(volume);
+
+                preset.active_volumes_mut().insert(volume_output_id.to_owned(), volume_level);
+                settings.volume_level = if settings.volume_as_percent {
+                    level_to_percent(volume, volume_level).to_string()
+                } else {
+                    volume_level.to_string()
+                };
+            }
+        }
+    }
+}
+
+// The saved settings with the preset of a trigger loaded, None when the preset no longer exists
+fn trigger_settings(trigger: &Trigger, outputs: &HashMap<String, Output>, settings: &GroupingSettings) -> Option<GroupingSettings> {
+    let mut settings = settings.to_owned();
+
+    settings.selected = Some(settings.presets.iter().position(|preset| preset.name == trigger.preset_name)?);
+    load_preset(&mut settings, outputs);
+    settings.action = trigger.action.to_owned();
+
+    Some(settings)
+}
+
+// Copies the usage of the triggered preset, leaving everything else as saved
+fn keep_usage(saved: &mut GroupingSettings, triggered: &GroupingSettings) {
+    let preset = triggered.selected.and_then(|selected| triggered.presets.get(selected));
+
+    if let Some(preset) = preset {
+        if let Some(saved) = saved.presets.iter_mut().find(|saved| saved.name == preset.name) {
+            saved.activation_count = preset.activation_count;
+            saved.last_activated_at = preset.last_activated_at.to_owned();
+            // A deactivation captures the volumes of a preset that uses the last used ones
+            saved.volumes = preset.volumes.to_owned();
+            saved.volume_percents = preset.volume_percents.to_owned();
+        }
+    }
+}
+
+pub fn describe_presets(presets: &[Preset], outputs: &HashMap<String, Output>) -> Vec<PresetSummary> {
+    presets
+        .iter()
+        .map(|preset| {
+            let members = preset.output_ids
+                .iter()
+                .map(|output_id| match outputs.get(output_id) {
+                    Some(output) => output.display_name.to_owned(),
+                    None => format!("{} (unavailable)", output_id)
+                })
+                .collect();
+            let volume_mode = match preset.volume_type {
+                VolumeType::Untouched => "untouched",
+                VolumeType::LastUsed => "last_used",
+                VolumeType::Preset => "preset"
+            };
+            // Active when all members share a zone that holds no other outputs
+            let zone_id = preset.output_ids
+                .first()
+                .and_then(|output_id| outputs.get(output_id))
+                .map(|output| output.zone_id.as_str());
+            let active = zone_id.map_or(false, |zone_id| {
+                let zone_size = outputs.values().filter(|output| output.zone_id == zone_id).count();
+
+                zone_size == preset.output_ids.len() && preset.output_ids.iter().all(|output_id| {
+                    outputs.get(output_id).map_or(false, |output| output.zone_id == zone_id)
+                })
+            });
+
+            PresetSummary {
+                name: preset.name.to_owned(),
+                members,
+                volume_mode: volume_mode.to_owned(),
+                active
+            }
+        })
+        .collect()
+}
+
+// Groups of preset indices that share the exact same set of outputs
+fn find_duplicates(presets: &[Preset]) -> Vec<Vec<usize>> {
+    let mut groups: Vec<(Vec<&String>, Vec<usize>)> = Vec::new();
+
+    for (index, preset) in presets.iter().enumerate() {
+        let mut output_ids: Vec<&String> = preset.output_ids.iter().collect();
+
+        output_ids.sort();
+        output_ids.dedup();
+
+        match groups.iter_mut().find(|(group_ids, _)| *group_ids == output_ids) {
+            Some((_, indices)) => indices.push(index),
+            None => groups.push((output_ids, vec![index]))
+        }
+    }
+
+    groups
+        .into_iter()
+        .map(|(_, indices)| indices)
+        .filter(|indices| indices.len() > 1)
+        .collect()
+}
+
+fn describe_duplicates(presets: &[Preset], duplicates: &[Vec<usize>]) -> String {
+    if duplicates.is_empty() {
+        return "No duplicate presets found".to_owned()
+    }
+
+    let groups: Vec<String> = duplicates
+        .iter()
+        .map(|indices| {
+            let mut names: Vec<String> = indices
+                .iter()
+                .map(|index| format!("'{}'", presets[*index].name))
+                .collect();
+            let last = names.pop().unwrap_or_default();
+
+            format!("{} and {} share the same outputs", names.join(", "), last)
+        })
+        .collect();
+
+    format!("Duplicates: {}", groups.join("; "))
+}
+
+// Keeps the first preset of each duplicate group and deletes the rest
+fn merge_duplicates(settings: &mut GroupingSettings) -> usize {
+    let mut removals: Vec<usize> = find_duplicates(&settings.presets)
+        .iter()
+        .flat_map(|indices| indices[1..].to_vec())
+        .collect();
+
+    removals.sort_unstable_by(|a, b| b.cmp(a));
+
+    for index in &removals {
+        settings.presets.remove(*index);
+    }
+
+    if !removals.is_empty() {
+        settings.selected = None;
+        settings.batch.clear();
+    }
+
+    removals.len()
+}
+
+// Drops the empty-named leftovers of aborted New Preset flows, keeping the selection and batch on their presets
+fn compact_presets(settings: &mut GroupingSettings) -> usize {
+    let mut new_indices = Vec::with_capacity(settings.presets.len());
+    let mut count = 0;
+
+    for preset in &settings.presets {
+        if preset.name.trim().is_empty() {
+            new_indices.push(None);
+        } else {
+            new_indices.push(Some(count));
+            count += 1;
+        }
+    }
+
+    let removed = settings.presets.len() - count;
+
+    if removed > 0 {
+        settings.presets.retain(|preset| !preset.name.trim().is_empty());
+        settings.selected = settings.selected.and_then(|selected| new_indices.get(selected).cloned().flatten());
+        settings.batch = settings.batch
+            .iter()
+            .filter_map(|index| new_indices.get(*index).cloned().flatten())
+            .collect();
+    }
+
+    removed
+}
+
+fn count_volume_type_changes(presets: &[Preset], volume_type: &VolumeType) -> usize {
+    presets
+        .iter()
+        .filter(|preset| preset.volume_type.to_owned() as usize != volume_type.to_owned() as usize)
+        .count()
+}
+
+fn set_volume_type_all(settings: &mut GroupingSettings) -> usize {
+    let count = count_volume_type_changes(&settings.presets, &settings.volume_type);
+
+    for preset in &mut settings.presets {
+        preset.volume_type = settings.volume_type.to_owned();
+
+        match preset.volume_type {
+            // Nothing gets restored, stored levels would only go stale
+            VolumeType::Untouched => {
+                preset.volumes.clear();
+                preset.volume_percents.clear();
+            }
+            // Captured levels are native, percentages don't apply
+            VolumeType::LastUsed => preset.volume_percents.clear(),
+            VolumeType::Preset => ()
+        }
+    }
+
+    count
+}
+
+// Picks the preset that wins a conflict, the highest priority wins and on equal
+// priority the preset that comes first in the list
+fn highest_priority<'a>(candidates: impl Iterator<Item = &'a Preset>) -> Option<&'a Preset> {
+    candidates.fold(None, |best: Option<&Preset>, preset| match best {
+        Some(best) if best.priority >= preset.priority => Some(best),
+        _ => Some(preset)
+    })
+}
+
+fn match_preset<'a, 'b>(presets: &'a [Preset], zones: &'b [Zone]) -> Option<(&'a Preset, &'b Zone)> {
+    let find_zone = |preset: &Preset| {
+        zones.iter().find(|zone| {
+            // Roon decides the order of outputs within a zone, match regardless of order
+            zone.outputs.len() == preset.output_ids.len()
+                && preset.output_ids
+                    .iter()
+                    .all(|output_id| zone.outputs.iter().any(|output| output.output_id == *output_id))
+        })
+    };
+    // Presets with the same outputs all match the same zone
+    let preset = highest_priority(presets.iter().filter(|preset| find_zone(preset).is_some()))?;
+
+    Some((preset, find_zone(preset)?))
+}
+
+// Zones that differ by a single output from a preset, the usual reason a preset isn't recognized
+fn find_near_misses<'a>(
+    presets: &[Preset],
+    zones: impl Iterator<Item = &'a Zone>,
+    aliases: &HashMap<String, String>
+) -> Vec<String> {
+    let mut near_misses = Vec::new();
+
+    for zone in zones.filter(|zone| zone.outputs.len() > 1) {
+        if match_preset(presets, std::slice::from_ref(zone)).is_some() {
+            continue
+        }
+
+        for preset in presets {
+            let extra: Vec<&str> = zone.outputs
+                .iter()
+                .filter(|output| !preset.output_ids.contains(&output.output_id))
+                .map(|output| aliased_name(aliases, &output.output_id, &output.display_name))
+                .collect();
+            let missing: Vec<&str> = preset.output_ids
+                .iter()
+                .filter(|output_id| !zone.outputs.iter().any(|output| output.output_id == **output_id))
+                .map(|output_id| {
+                    let name = preset.output_names.get(output_id).map_or(output_id.as_str(), |name| name.as_str());
+
+                    aliased_name(aliases, output_id, name)
+                })
+                .collect();
+
+            if let [output_name] = [extra, missing].concat().as_slice() {
+                near_misses.push(format!(
+                    "Zone \"{}\" nearly matches \"{}\" (differs by output {})",
+                    zone.display_name,
+                    preset.name,
+                    output_name
+                ));
+            }
+        }
+    }
+
+    near_misses
+}
+
+// Outputs can only be in one group, presets sharing one can't be active at the same time
+fn find_overlaps(
+    presets: &[Preset],
+    index: usize,
+    outputs: &HashMap<String, Output>,
+    aliases: &HashMap<String, String>
+) -> Vec<String> {
+    let preset = match presets.get(index) {
+        Some(preset) => preset,
+        None => return Vec::new()
+    };
+    let mut overlaps = Vec::new();
+
+    for (_, other) in presets.iter().enumerate().filter(|(other_index, _)| *other_index != index) {
+        for output_id in preset.output_ids.iter().filter(|output_id| other.output_ids.contains(*output_id)) {
+            let name = outputs
+                .get(output_id)
+                .map(|output| output.display_name.as_str())
+                .or_else(|| preset.output_names.get(output_id).map(|name| name.as_str()))
+                .unwrap_or(output_id.as_str());
+            let name = aliased_name(aliases, output_id, name);
+
+            overlaps.push(format!(
+                "Presets \"{}\" and \"{}\" both use {}; only one can be active at a time",
+                preset.name,
+                other.name,
+                name
+            ));
+        }
+    }
+
+    overlaps
+}
+
+// What matching depends on, other preset changes don't affect which zone matches
+fn membership(presets: &[Preset]) -> Vec<(&str, &[String], i32)> {
+    presets
+        .iter()
+        .map(|preset| (preset.name.as_str(), preset.output_ids.as_slice(), preset.priority))
+        .collect()
+}
+
+// Members ungrouped in the Roon app leave a zone that no longer represents the preset, a zone
+// missing from the update is left to the grace period
+fn is_split(matched: &MatchedZone, presets: &[Preset], zones: &[Zone]) -> bool {
+    let zone = zones.iter().find(|zone| zone.zone_id == matched.zone_id);
+    let preset = presets.iter().find(|preset| preset.name == matched.preset_name);
+
+    match (zone, preset) {
+        (Some(zone), Some(preset)) => match_preset(std::slice::from_ref(preset), std::slice::from_ref(zone)).is_none(),
+        _ => false
+    }
+}
+
+fn find_live_zone<'a>(preset: &Preset, zones: &'a [Zone]) -> Option<&'a Zone> {
+    if let Some((_, zone)) = match_preset(std::slice::from_ref(preset), zones) {
+        return Some(zone)
+    }
+
+    // The group may have drifted, fall back to the group holding the primary output
+    let primary_output_id = preset.output_ids.first()?;
+
+    zones.iter().find(|zone| {
+        zone.outputs.len() > 1 && zone.outputs.iter().any(|output| output.output_id == *primary_output_id)
+    })
+}
+
+// Roon lists the output that leads the group first
+fn volume_master_name(zone: &Zone) -> Option<&str> {
+    zone.outputs.first().map(|output| output.display_name.as_str())
+}
+
+fn master_mismatch(preset: &Preset, zone: &Zone) -> Option<String> {
+    let master = zone.outputs.first()?;
+    let expected_id = preset.leading_output_id()?;
+
+    if master.output_id == *expected_id {
+        return None
+    }
+
+    let expected = zone.outputs
+        .iter()
+        .find(|output| output.output_id == *expected_id)
+        .map_or(expected_id.as_str(), |output| output.display_name.as_str());
+
+    Some(format!("(master is {}, preset expects {})", master.display_name, expected))
+}
+
+fn state_name(state: &State) -> &'static str {
+    match state {
+        State::Playing => "playing",
+        State::Paused => "paused",
+        State::Loading => "loading",
+        State::Stopped => "stopped"
+    }
+}
+
+fn matched_status(preset: &Preset, zone: &Zone, template: &str) -> String {
+    // The API has no way to rename a zone, the label only replaces the name Roon derives
+    let zone_name = preset.zone_label.as_deref().unwrap_or(&zone.display_name);
+    let mut status_msg = render_status(template, STATUS_MATCHED, &[
+        ("zone", zone_name.to_owned()),
+        ("preset", preset.name.to_owned()),
+        ("state", state_name(&zone.state).to_owned()),
+        ("master", volume_master_name(zone).unwrap_or_default().to_owned())
+    ]);
+
+    if let Some(note) = master_mismatch(preset, zone) {
+        status_msg.push(' ');
+        status_msg.push_str(&note);
+    }
+
+    status_msg
+}
+
+fn missing_outputs(output_ids: &[String], zones: &HashMap<String, Zone>) -> Vec<String> {
+    // Look for the outputs in the zone that holds the primary output
+    let zone = output_ids.first().and_then(|primary_output_id| {
+        zones.values().find(|zone| zone.outputs.iter().any(|output| output.output_id == *primary_output_id))
+    });
+
+    output_ids
+        .iter()
+        .filter(|output_id| {
+            zone.map_or(true, |zone| !zone.outputs.iter().any(|output| output.output_id == **output_id))
+        })
+        .cloned()
+        .collect()
+}
+
+fn extract_preset(zones: &Vec<Zone>) -> Option<Preset> {
+    for zone in zones {
+        if zone.outputs.len() > 1 {
+            let mut preset = Preset::default();
+
+            preset.name = zone.display_name.to_owned();
+
+            for output in &zone.outputs {
+                preset.output_ids.push(output.output_id.to_owned());
+            }
+
+            return Some(preset)
+        }
+    }
+
+    None
+}
+
+// Other groups holding members of the preset, these have to be dissolved before grouping
+fn find_busy_zones<'a>(output_ids: &[&str], zones: &'a [Zone]) -> Vec<&'a Zone> {
+    zones
+        .iter()
+        .filter(|zone| zone.outputs.len() > 1)
+        .filter(|zone| zone.outputs.iter().any(|output| output_ids.contains(&output.output_id.as_str())))
+        .filter(|zone| {
+            // The preset itself, already grouped as it should be
+            zone.outputs.len() != output_ids.len()
+                || !zone.outputs.iter().all(|output| output_ids.contains(&output.output_id.as_str()))
+        })
+        .collect()
+}
+
+fn find_incompatible_outputs<'a>(output_ids: &[&'a str], outputs: &HashMap<String, Output>) -> Vec<&'a str> {
+    let primary_output_id = match output_ids.first() {
+        Some(primary_output_id) => *primary_output_id,
+        None => return Vec::new()
+    };
+    let can_group_with_output_ids = outputs.get(primary_output_id)
+        .map(|output| output.can_group_with_output_ids.as_slice())
+        .unwrap_or_default();
+
+    output_ids[1..]
+        .iter()
+        .filter(|output_id| !can_group_with_output_ids.iter().any(|id| id == *output_id))
+        .copied()
+        .collect()
+}
+
+// Volume changes that make the other members follow the primary output of a mirror preset
+fn plan_mirror(
+    preset: &Preset,
+    changed_ids: &[String],
+    outputs: &HashMap<String, Output>,
+    safety_cap: SafetyCap
+) -> Vec<TransportCommand> {
+    let primary_volume = preset.output_ids
+        .first()
+        .filter(|primary_output_id| preset.mirror && changed_ids.contains(*primary_output_id))
+        .and_then(|primary_output_id| outputs.get(primary_output_id)?.volume.as_ref())
+        .filter(|volume| !matches!(volume.scale, Scale::Incremental));
+    let percent = match primary_volume {
+        Some(volume) => level_to_percent(volume, live_volume_level(volume)),
+        None => return Vec::new()
+    };
+
+    preset.output_ids[1..]
+        .iter()
+        .filter_map(|output_id| {
+            let volume = outputs
+                .get(output_id)?
+                .volume
+                .as_ref()
+                .filter(|volume| !matches!(volume.scale, Scale::Incremental))?;
+            let level = safety_cap.limit(volume, percent_to_level(volume, percent));
+
+            (!volume_reached(volume, level)).then(|| TransportCommand::ChangeVolume(output_id.to_owned(), level, volume_step(volume)))
+        })
+        .collect()
+}
+
+// The source control that can bring the output out of standby
+fn standby_control(output: &Output) -> Option<&str> {
+    output.source_controls
+        .as_ref()?
+        .iter()
+        .find(|control| control.supports_standby && control.status == "standby")
+        .map(|control| control.control_key.as_str())
+}
+
+// Wakes the members in standby, with a pause to let them come up before grouping
+fn plan_power_on(output_ids: &[&str], outputs: &HashMap<String, Output>) -> Vec<TransportCommand> {
+    let mut commands: Vec<TransportCommand> = output_ids
+        .iter()
+        .filter_map(|output_id| {
+            let control_key = standby_control(outputs.get(*output_id)?)?;
+
+            Some(TransportCommand::PowerOn(output_id.to_string(), control_key.to_owned()))
+        })
+        .collect();
+
+    if !commands.is_empty() {
+        commands.push(TransportCommand::Wait(POWER_ON_DELAY));
+    }
+
+    commands
+}
+
+// Members that are offline or in standby without a way to switch them on
+fn find_unwakeable_members<'a>(preset: &'a Preset, outputs: &'a HashMap<String, Output>) -> Vec<&'a str> {
+    preset.output_ids
+        .iter()
+        .filter_map(|output_id| match outputs.get(output_id) {
+            Some(output) => {
+                let is_standby = output.source_controls
+                    .as_ref()
+                    .map_or(false, |controls| controls.iter().any(|control| control.status == "standby"));
+
+                (is_standby && standby_control(output).is_none()).then(|| output.display_name.as_str())
+            }
+            None => Some(preset.output_names.get(output_id).map_or(output_id.as_str(), |name| name.as_str()))
+        })
+        .collect()
+}
+
+// The level each member is to get before clamping and the safety cap
+fn target_levels<'a>(
+    preset: &Preset,
+    output_ids: &[&str],
+    outputs: &'a HashMap<String, Output>,
+    last_volumes: &HashMap<String, f32>
+) -> Vec<(&'a Output, &'a Volume, f32)> {
+    let mut volumes = match preset.volume_type {
+        VolumeType::Untouched => return Vec::new(),
+        // Outputs without a captured level fall back to their last known level
+        VolumeType::LastUsed => last_volumes.to_owned(),
+        VolumeType::Preset => HashMap::new()
+    };
+
+    volumes.extend(preset.active_volumes().to_owned());
+
+    output_ids
+        .iter()
+        .filter_map(|output_id| {
+            // Incremental outputs have no absolute level to restore
+            let output = outputs.get(*output_id)?;
+            let volume = output.volume
+                .as_ref()
+                .filter(|volume| !matches!(volume.scale, Scale::Incremental))?;
+            let value = *volumes.get(*output_id)?;
+            // Scale within the range of the output, this also works for negative dB levels
+            let value = match preset.master_scale {
+                Some(scale) => percent_to_level(volume, (level_to_percent(volume, value) * scale).clamp(0.0, 100.0)),
+                None => value
+            };
+
+            Some((output, volume, value))
+        })
+        .collect()
+}
+
+fn plan_volumes(
+    preset: &Preset,
+    output_ids: &[&str],
+    outputs: &HashMap<String, Output>,
+    last_volumes: &HashMap<String, f32>,
+    safety_cap: SafetyCap
+) -> Vec<TransportCommand> {
+    target_levels(preset, output_ids, outputs, last_volumes)
+        .into_iter()
+        .map(|(output, volume, value)| {
+            // The range may have changed since the level was stored, the core rejects levels outside of it
+            let clamped = value.clamp(volume.hard_limit_min, volume.hard_limit_max);
+
+            if clamped != value {
+                println!("Clamped volume of {} from {} to {}", output.display_name, value, clamped);
+            }
+
+            let capped = safety_cap.limit(volume, clamped);
+
+            if capped != clamped {
+                println!("Safety cap limited volume of {} from {} to {}", output.display_name, clamped, capped);
+            }
+
+            TransportCommand::ChangeVolume(output.output_id.to_owned(), capped, volume_step(volume))
+        })
+        .collect()
+}
+
+// Spreads the volume changes over the fade time, each output moves from its own level
+// towards its own target so all arrive at the same time, whether going up or down
+fn ramp_volumes(volume_commands: &[TransportCommand], outputs: &HashMap<String, Output>, fade_ms: u32) -> Vec<TransportCommand> {
+    let ramps: Vec<(&String, f32, f32, Option<&Volume>)> = volume_commands
+        .iter()
+        .filter_map(|command| match command {
+            TransportCommand::ChangeVolume(output_id, target, _) => {
+                let volume = outputs.get(output_id).and_then(|output| output.volume.as_ref());
+                let start = volume.map_or(*target, live_volume_level);
+
+                Some((output_id, start, *target, volume))
+            }
+            _ => None
+        })
+        .collect();
+    let mut commands = Vec::new();
+
+    for step in 1..=FADE_STEPS {
+        for (output_id, start, target, volume) in &ramps {
+            let value = if step == FADE_STEPS {
+                *target
+            } else {
+                let value = start + (target - start) * step as f32 / FADE_STEPS as f32;
+
+                volume.map_or(value, |volume| snap_to_step(volume, value))
+            };
+
+            commands.push(TransportCommand::ChangeVolume(output_id.to_string(), value, volume.map_or(1.0, volume_step)));
+        }
+
+        if step < FADE_STEPS {
+            commands.push(TransportCommand::Wait(Duration::from_millis((fade_ms / FADE_STEPS) as u64)));
+        }
+    }
+
+    commands
+}
+
+fn plan_activate(input: &PlanInput) -> Vec<TransportCommand> {
+    let PlanInput { presets, selected, extracted_preset, output_ids, zones, outputs, last_volumes, safety_cap } = *input;
+    let mut commands = Vec::new();
+    let preset = match presets.get(selected) {
+        Some(preset) => preset,
+        None => return commands
+    };
+
+    // Deactivate any active grouping
+    if let Some(extracted_preset) = extracted_preset {
+        commands.push(TransportCommand::Ungroup(extracted_preset.output_ids.to_owned()));
+    }
+
+    if preset.exclusive {
+        // Only one exclusive configuration should be live
+        for (_, other) in presets.iter().enumerate().filter(|(index, _)| *index != selected) {
+            if let Some((_, zone)) = match_preset(std::slice::from_ref(other), zones) {
+                let other_output_ids = zone.outputs.iter().map(|output| output.output_id.to_owned()).collect();
+
+                commands.push(TransportCommand::Ungroup(other_output_ids));
+            }
+        }
+    }
+
+    for zone in find_busy_zones(output_ids, zones) {
+        let busy_ids: Vec<String> = zone.outputs.iter().map(|output| output.output_id.to_owned()).collect();
+        let is_planned = commands.iter().any(|command| matches!(command, TransportCommand::Ungroup(output_ids) if *output_ids == busy_ids));
+
+        if !is_planned {
+            commands.push(TransportCommand::Ungroup(busy_ids));
+        }
+    }
+
+    if preset.power_on_members {
+        commands.extend(plan_power_on(output_ids, outputs));
+    }
+
+    let volume_commands = plan_volumes(preset, output_ids, outputs, last_volumes, safety_cap);
+    let volume_commands = match preset.fade_ms {
+        Some(fade_ms) => ramp_volumes(&volume_commands, outputs, fade_ms),
+        None => volume_commands
+    };
+
+    if preset.volume_delay_ms.is_none() {
+        commands.extend(volume_commands.to_owned());
+    }
+
+    let mut output_ids: Vec<String> = output_ids.iter().map(|output_id| output_id.to_string()).collect();
+
+    // Room sets and the editor may have reordered the members, the primary leads
+    if let Some(primary_output_id) = preset.output_ids.first() {
+        if let Some(index) = output_ids.iter().position(|output_id| output_id == primary_output_id) {
+            let primary_output_id = output_ids.remove(index);
+
+            output_ids.insert(0, primary_output_id);
+        }
+    }
+
+    // The API has no call to designate a volume master. What group_outputs does guarantee is that the
+    // zone of the first output is kept, so the volume master leads the group instead of the primary.
+    if let Some(volume_master) = preset.volume_master.as_ref() {
+        if let Some(index) = output_ids.iter().position(|output_id| output_id == volume_master) {
+            let volume_master = output_ids.remove(index);
+
+            output_ids.insert(0, volume_master);
+        }
+    }
+
+    match preset.stagger_ms {
+        Some(stagger_ms) if output_ids.len() > 2 => {
+            // Add members one by one for devices that fail to join all at once
+            for count in 2..=output_ids.len() {
+                commands.push(TransportCommand::Group(output_ids[..count].to_vec()));
+
+                if count < output_ids.len() {
+                    commands.push(TransportCommand::Wait(Duration::from_millis(stagger_ms as u64)));
+                }
+            }
+        }
+        _ => {
+            commands.push(TransportCommand::Group(output_ids));
+        }
+    }
+
+    if let Some(volume_delay_ms) = preset.volume_delay_ms {
+        // Some outputs ignore volume changes while still negotiating the group
+        if !volume_commands.is_empty() {
+            commands.push(TransportCommand::Wait(Duration::from_millis(volume_delay_ms as u64)));
+            commands.extend(volume_commands);
+        }
+    }
+
+    commands
+}
+
+fn plan_deactivate(preset: Option<&Preset>, output_ids: &[&str], zones: &[Zone]) -> Vec<TransportCommand> {
+    // Prefer the live arrangement of the matched zone over the stored ids
+    let ungroup_ids = match preset.and_then(|preset| find_live_zone(preset, zones)) {
+        Some(zone) => zone.outputs.iter().map(|output| output.output_id.to_owned()).collect(),
+        None => output_ids.iter().map(|output_id| output_id.to_string()).collect()
+    };
+
+    vec![TransportCommand::Ungroup(ungroup_ids)]
+}
+
+// The levels of the live group to keep on deactivation, nothing is captured when the group is gone
+// as its outputs may be in use elsewhere by now
+fn captured_volumes(preset: &Preset, zones: &[Zone], outputs: &HashMap<String, Output>) -> Vec<(String, f32)> {
+    let zone = match find_live_zone(preset, zones) {
+        Some(zone) => zone,
+        None => return Vec::new()
+    };
+
+    zone.outputs
+        .iter()
+        .filter_map(|output| {
+            let volume = outputs.get(&output.output_id)?.volume.as_ref()?;
+
+            Some((output.output_id.to_owned(), live_volume_level(volume)))
+        })
+        .collect()
+}
+
+fn plan_ungroup_all(zones: &[Zone]) -> Vec<TransportCommand> {
+    zones
+        .iter()
+        .filter(|zone| zone.outputs.len() > 1)
+        .map(|zone| TransportCommand::Ungroup(zone.outputs.iter().map(|output| output.output_id.to_owned()).collect()))
+        .collect()
+}
+
+// The zones as the commands leave them, so a batch can plan the next preset before Roon reports the zones
+fn apply_commands(zones: &mut Vec<Zone>, commands: &[TransportCommand]) {
+    for command in commands {
+        let output_ids = match command {
+            TransportCommand::Group(output_ids) | TransportCommand::Ungroup(output_ids) => output_ids,
+            _ => continue
+        };
+        let mut members: Vec<(Zone, Output)> = Vec::new();
+
+        for output_id in output_ids {
+            for zone in zones.iter_mut() {
+                if let Some(index) = zone.outputs.iter().position(|output| output.output_id == *output_id) {
+                    let output = zone.outputs.remove(index);
+
+                    members.push((zone.to_owned(), output));
+                    break
+                }
+            }
+        }
+
+        zones.retain(|zone| !zone.outputs.is_empty());
+
+        if let TransportCommand::Group(_) = command {
+            if let Some((zone, _)) = members.first() {
+                let mut zone = zone.to_owned();
+
+                zone.outputs = members.iter().map(|(_, output)| output.to_owned()).collect();
+                zones.push(zone);
+            }
+        } else {
+            for (mut zone, output) in members {
+                zone.zone_id = output.zone_id.to_owned();
+                zone.display_name = output.display_name.to_owned();
+                zone.outputs = vec![output];
+                zones.push(zone);
+            }
+        }
+    }
+}
+
+// Mutes the whole group when any member is still audible, otherwise unmutes all
+fn plan_toggle_mute(zone: &Zone) -> Vec<TransportCommand> {
+    let members: Vec<&Output> = zone.outputs.iter().filter(|output| output.volume.is_some()).collect();
+    let mute_all = members
+        .iter()
+        .any(|output| output.volume.as_ref().map_or(false, |volume| volume.is_muted != Some(true)));
+
+    members
+        .iter()
+        .map(|output| TransportCommand::Mute(output.output_id.to_owned(), mute_all))
+        .collect()
+}
+
+// Household wide bounds in % of the range of each output, every planned volume stays within them
+#[derive(Clone, Copy, Debug, Default)]
+struct SafetyCap {
+    min_percent: Option<u32>,
+    max_percent: Option<u32>
+}
+
+impl SafetyCap {
+    fn bounds(&self, volume: &Volume) -> (f32, f32) {
+        (
+            self.min_percent.map_or(volume.hard_limit_min, |percent| percent_to_level(volume, percent as f32)),
+            self.max_percent.map_or(volume.hard_limit_max, |percent| percent_to_level(volume, percent as f32))
+        )
+    }
+
+    fn limit(&self, volume: &Volume, value: f32) -> f32 {
+        let (min, max) = self.bounds(volume);
+
+        value.clamp(min, max.max(min))
+    }
+
+    // Members whose level the cap changes, to note in the status
+    fn engaged(
+        &self,
+        preset: &Preset,
+        output_ids: &[&str],
+        outputs: &HashMap<String, Output>,
+        last_volumes: &HashMap<String, f32>,
+        aliases: &HashMap<String, String>
+    ) -> Vec<String> {
+        target_levels(preset, output_ids, outputs, last_volumes)
+            .into_iter()
+            .filter(|(_, volume, value)| {
+                let level = value.clamp(volume.hard_limit_min, volume.hard_limit_max);
+
+                self.limit(volume, level) != level
+            })
+            .map(|(output, _, _)| aliased_name(aliases, &output.output_id, &output.display_name).to_owned())
+            .collect()
+    }
+}
+
+// Seconds left before the preset may be activated again
+fn cooldown_left(preset: &Preset, last_activations: &HashMap<String, Instant>) -> Option<u64> {
+    let cooldown = Duration::from_secs(preset.cooldown_seconds? as u64);
+    let elapsed = last_activations.get(&preset.name)?.elapsed();
+
+    (elapsed < cooldown).then(|| (cooldown - elapsed).as_secs().max(1))
+}
+
+fn note_safety_cap(status_msg: &mut String, capped: &[String]) {
+    if !capped.is_empty() {
+        status_msg.push_str(&format!(", safety cap engaged for {}", capped.join(", ")));
+    }
+}
+
+// Everything an action needs to know to plan its transport commands
+struct PlanInput<'a> {
+    presets: &'a [Preset],
+    selected: usize,
+    extracted_preset: Option<&'a Preset>,
+    output_ids: &'a [&'a str],
+    zones: &'a [Zone],
+    outputs: &'a HashMap<String, Output>,
+    last_volumes: &'a HashMap<String, f32>,
+    safety_cap: SafetyCap
+}
+
+// The transport commands that execute an action, actions that only store settings plan nothing
+fn plan_action(action: &Action, input: &PlanInput) -> Vec<TransportCommand> {
+    match action {
+        Action::Activate => plan_activate(input),
+        Action::Deactivate => plan_deactivate(input.presets.get(input.selected), input.output_ids, input.zones),
+        Action::UngroupAll => plan_ungroup_all(input.zones),
+        _ => Vec::new()
+    }
+}
+
+// The complete set of grouped outputs, the last group command holds all members
+fn grouped_output_ids(commands: &[TransportCommand]) -> Vec<String> {
+    commands
+        .iter()
+        .rev()
+        .find_map(|command| match command {
+            TransportCommand::Group(output_ids) => Some(output_ids.to_owned()),
+            _ => None
+        })
+        .unwrap_or_default()
+}
+
+fn ungrouped_output_ids(commands: &[TransportCommand]) -> Vec<String> {
+    commands
+        .iter()
+        .filter_map(|command| match command {
+            TransportCommand::Ungroup(output_ids) => Some(output_ids.to_owned()),
+            _ => None
+        })
+        .flatten()
+        .collect()
+}
+
+// Drives the futures concurrently until all are done, so no crate is needed for a join
+async fn join_all<F: Future>(futures: Vec<F>) {
+    let mut futures: Vec<Option<std::pin::Pin<Box<F>>>> = futures.into_iter().map(|future| Some(Box::pin(future))).collect();
+
+    std::future::poll_fn(|cx| {
+        let mut is_pending = false;
+
+        for slot in futures.iter_mut() {
+            if let Some(future) = slot {
+                if future.as_mut().poll(cx).is_ready() {
+                    *slot = None;
+                } else {
+                    is_pending = true;
+                }
+            }
+        }
+
+        if is_pending { Poll::Pending } else { Poll::Ready(()) }
+    }).await
+}
+
+// The outputs take their volume changes together, the requests of a single output stay in order
+async fn change_volumes<'a, F, Fut>(commands: &'a [TransportCommand], change: F)
+where
+    F: Fn(&'a str, &'static str, i32) -> Fut,
+    Fut: Future
+{
+    let change = &change;
+    let changes = commands
+        .iter()
+        .filter_map(|command| match command {
+            TransportCommand::ChangeVolume(output_id, value, step) => Some(async move {
+                for (how, value) in volume_requests(*value, *step) {
+                    change(output_id, how, value).await;
+                }
+            }),
+            _ => None
+        })
+        .collect();
+
+    join_all(changes).await
+}
+
+async fn execute_commands(transport: &Transport, commands: &[TransportCommand]) {
+    let mut index = 0;
+
+    while index < commands.len() {
+        // Consecutive volume changes go out together, so the last output doesn't lag behind the first
+        let volume_count = commands[index..]
+            .iter()
+            .take_while(|command| matches!(command, TransportCommand::ChangeVolume(..)))
+            .count();
+
+        if volume_count > 1 {
+            change_volumes(&commands[index..index + volume_count], |output_id, how, value| {
+                transport.change_volume(output_id, how, value)
+            }).await;
+            index += volume_count;
+            continue
+        }
+
+        let command = &commands[index];
+
+        index += 1;
+
+        match command {
+            TransportCommand::Group(output_ids) => {
+                transport.group_outputs(output_ids.iter().map(|output_id| output_id.as_str()).collect()).await;
+            }
+            TransportCommand::Ungroup(output_ids) => {
+                transport.ungroup_outputs(output_ids.iter().map(|output_id| output_id.as_str()).collect()).await;
+            }
+            TransportCommand::ChangeVolume(output_id, value, step) => {
+                for (how, value) in volume_requests(*value, *step) {
+                    transport.change_volume(output_id, how, value).await;
+                }
+            }
+            TransportCommand::Mute(output_id, is_muted) => {
+                let how = if *is_muted { Mute::Mute } else { Mute::Unmute };
+
+                transport.mute(output_id, &how).await;
+            }
+            TransportCommand::PowerOn(output_id, control_key) => {
+                transport.convenience_switch(output_id, Some(control_key)).await;
+            }
+            TransportCommand::Wait(duration) => {
+                tokio::time::sleep(*duration).await;
+            }
+        }
+    }
+}
+
+fn track_volumes(pending_volumes: &mut HashMap<String, PendingVolume>, commands: &[TransportCommand]) {
+    let deadline = tokio::time::Instant::now() + VOLUME_RETRY_DELAY;
+
+    for command in commands {
+        if let TransportCommand::ChangeVolume(output_id, target, step) = command {
+            pending_volumes.insert(output_id.to_owned(), PendingVolume { target: *target, step: *step, attempts: 0, deadline });
+        }
+    }
+}
+
+fn volume_reached(volume: &Volume, target: f32) -> bool {
+    (live_volume_level(volume) - target).abs() <= volume_step(volume) / 2.0
+}
+
+// One line per member, for verbose mode
+fn activation_detail(
+    pending: &PendingGroup,
+    missing: &[String],
+    pending_volumes: &HashMap<String, PendingVolume>,
+    outputs: &HashMap<String, Output>,
+    aliases: &HashMap<String, String>
+) -> String {
+    let mut lines = vec![format!("Preset \"{}\" activated", pending.preset_name)];
+
+    for output_id in &pending.output_ids {
+        let name = outputs.get(output_id).map_or(output_id.as_str(), |output| output.display_name.as_str());
+        let name = aliased_name(aliases, output_id, name);
+        let group_state = if missing.contains(output_id) { "not joined" } else { "joined" };
+        let volume_state = if !pending.volume_ids.contains(output_id) {
+            "volume untouched"
+        } else if pending_volumes.contains_key(output_id) {
+            "volume pending"
+        } else {
+            "volume set"
+        };
+
+        lines.push(format!("{}: {}, {}", name, group_state, volume_state));
+    }
+
+    lines.join("\n")
+}
+
+// The write runs in the background, the handle lets it finish before exiting. Nothing is logged
+// unless the EVENT_LOG environment variable names the file
+fn log_event(preset_name: &str, action: &str, output_ids: &[String]) -> Option<tokio::task::JoinHandle<()>> {
+    let path = std::env::var("EVENT_LOG").ok()?;
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs());
+    let event = json!({
+        "timestamp": timestamp,
+        "preset": preset_name,
+        "action": action,
+        "output_ids": output_ids
+    });
+
+    Some(tokio::task::spawn_blocking(move || {
+        let result = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .and_then(|mut file| writeln!(file, "{}", event));
+
+        if let Err(err) = result {
+            println!("Failed to write event to {}: {}", path, err);
+        }
+    }))
+}
+
+// What an activation reads from the event loop and the pending work it leaves there
+struct ActivationState<'a> {
+    transport: &'a Transport,
+    browse: Option<&'a Browse>,
+    outputs: &'a Mutex<HashMap<String, Output>>,
+    matched_zone: Option<&'a MatchedZone>,
+    last_volumes: &'a HashMap<String, f32>,
+    is_legacy_settings: bool,
+    pending_groups: &'a mut Vec<PendingGroup>,
+    pending_volumes: &'a mut HashMap<String, PendingVolume>,
+    pending_waits: &'a mut Vec<PendingWait>,
+    pending_play: &'a mut Option<PendingPlay>,
+    last_activations: &'a mut HashMap<String, Instant>,
+    log_writes: &'a mut Vec<tokio::task::JoinHandle<()>>
+}
+
+// How a preset came to be activated
+#[derive(Clone, Copy)]
+enum Activation<'a> {
+    Single,
+    // Postponed until its members came online, with the members that didn't
+    Resumed(&'a [String]),
+    // One of the presets of Activate Multiple
+    Batch
+}
+
+// Activates a single preset, on its own as well as within Activate Multiple, returns the status
+// and whether it failed. The zones are brought up to date with the commands sent, for the
+// presets that follow in a batch
+async fn activate_preset(
+    state: &mut ActivationState<'_>,
+    settings: &mut GroupingSettings,
+    nv_settings: &mut Value,
+    presets: &[Preset],
+    selected: usize,
+    zones: &mut Vec<Zone>,
+    activation: Activation<'_>
+) -> (String, bool) {
+    let preset = match presets.get(selected) {
+        Some(preset) => preset,
+        None => return ("Select a preset to activate".to_owned(), true)
+    };
+    let member_ids: Vec<&str> = preset.output_ids.iter().map(|output_id| output_id.as_str()).collect();
+    let output_ids: Vec<&str> = {
+        let output_list = state.outputs.lock().unwrap();
+
+        member_ids.iter().copied().filter(|output_id| output_list.contains_key(*output_id)).collect()
+    };
+
+    if let Some(secs_left) = cooldown_left(preset, state.last_activations) {
+        println!("Ignoring activation of \"{}\" during its cooldown", preset.name);
+
+        return (format!("Preset \"{}\" ignored, cooling down ({}s left)", preset.name, secs_left), false)
+    }
+
+    // Offline members of a preset that has outputs here are expected, only a preset
+    // without a single output on this core was saved while connected to another one
+    if state.is_legacy_settings && output_ids.is_empty() {
+        let status_msg = format!(
+            "Preset \"{}\" can't be activated, outputs not found on this core: {}",
+            preset.name,
+            member_ids.join(", ")
+        );
+
+        return (status_msg, true)
+    }
+
+    let is_resumed = matches!(activation, Activation::Resumed(_));
+
+    if let Some(wait_ms) = preset.wait_for_outputs_ms.filter(|_| !is_resumed && output_ids.len() < member_ids.len()) {
+        state.pending_waits.retain(|pending| pending.preset_name != preset.name);
+        state.pending_waits.push(PendingWait {
+            preset_name: preset.name.to_owned(),
+            output_ids: preset.output_ids.to_owned(),
+            deadline: tokio::time::Instant::now() + Duration::from_millis(wait_ms as u64)
+        });
+
+        return (format!("Waiting for the outputs of \"{}\"", preset.name), false)
+    }
+
+    let incompatible: Vec<String> = {
+        let output_list = state.outputs.lock().unwrap();
+
+        find_incompatible_outputs(&output_ids, &output_list)
+            .iter()
+            .filter_map(|output_id| output_list.get(*output_id))
+            .map(|output| settings.output_name(output).to_owned())
+            .collect()
+    };
+    let is_active = state.matched_zone.map_or(false, |matched| matched.preset_name == preset.name);
+    let busy_zones: Vec<String> = find_busy_zones(&output_ids, zones)
+        .iter()
+        .map(|zone| zone.display_name.to_owned())
+        .collect();
+
+    if output_ids.is_empty() {
+        return (format!("Preset \"{}\" has no outputs available", preset.name), true)
+    }
+
+    if !incompatible.is_empty() {
+        let status_msg = format!(
+            "Preset \"{}\" can't be activated, incompatible outputs: {}",
+            preset.name,
+            incompatible.join(", ")
+        );
+
+        return (status_msg, true)
+    }
+
+    if settings.refuse_busy_outputs && !is_active && !busy_zones.is_empty() {
+        let status_msg = format!(
+            "Preset \"{}\" can't be activated, outputs in use by: {}",
+            preset.name,
+            busy_zones.join(", ")
+        );
+
+        return (status_msg, true)
+    }
+
+    // Re-applying volumes and dissolving the extracted grouping are left out of a batch
+    let is_batch = matches!(activation, Activation::Batch);
+
+    if is_active && !is_batch && settings.force {
+        let output_list = state.outputs.lock().unwrap().to_owned();
+        let commands = plan_volumes(preset, &output_ids, &output_list, state.last_volumes, settings.safety_cap());
+        let mut status_msg = format!("Preset \"{}\" is already active, volumes re-applied", preset.name);
+
+        note_safety_cap(&mut status_msg, &settings.safety_cap().engaged(preset, &output_ids, &output_list, state.last_volumes, &settings.output_aliases));
+        execute_commands(state.transport, &commands).await;
+        track_volumes(state.pending_volumes, &commands);
+
+        return (status_msg, false)
+    }
+
+    if is_active {
+        return (format!("Preset \"{}\" is already active", preset.name), false)
+    }
+
+    let commands = plan_activate(&PlanInput {
+        presets,
+        selected,
+        extracted_preset: settings.extracted_preset.as_ref().filter(|_| !is_batch),
+        output_ids: &output_ids,
+        zones,
+        outputs: &state.outputs.lock().unwrap(),
+        last_volumes: state.last_volumes,
+        safety_cap: settings.safety_cap()
+    });
+    let group_ids = grouped_output_ids(&commands);
+    let volume_ids = commands
+        .iter()
+        .filter_map(|command| match command {
+            TransportCommand::ChangeVolume(output_id, _, _) => Some(output_id.to_owned()),
+            _ => None
+        })
+        .collect();
+
+    state.log_writes.retain(|write| !write.is_finished());
+    state.log_writes.extend(log_event(&preset.name, "activate", &group_ids));
+    state.last_activations.insert(preset.name.to_owned(), Instant::now());
+
+    let started = Instant::now();
+
+    execute_commands(state.transport, &commands).await;
+    track_volumes(state.pending_volumes, &commands);
+    apply_commands(zones, &commands);
+
+    // The timeout starts once the last command is sent, staggered ones take a while
+    state.pending_groups.retain(|pending| pending.preset_name != preset.name);
+    state.pending_groups.push(PendingGroup {
+        preset_name: preset.name.to_owned(),
+        output_ids: group_ids,
+        volume_ids,
+        started,
+        deadline: tokio::time::Instant::now() + GROUP_TIMEOUT
+    });
+
+    if let (Some(browse), Some(station), Some(output_id)) = (state.browse, preset.play_item.to_owned(), output_ids.first()) {
+        *state.pending_play = Some(PendingPlay {
+            preset_name: preset.name.to_owned(),
+            station,
+            output_id: output_id.to_string(),
+            step: PlayStep::Station
+        });
+
+        // Start at the top of Live Radio, the stations are listed there
+        let opts = BrowseOpts {
+            hierarchy: RADIO_HIERARCHY.to_owned(),
+            pop_all: true,
+            ..Default::default()
+        };
+
+        browse.browse(&opts).await;
+    }
+
+    if let Some(stored) = settings.presets.get_mut(selected) {
+        let saved = &mut nv_settings["presets"][selected];
+
+        stored.activation_count += 1;
+        stored.last_activated_at = Some(format_date(SystemTime::now()));
+        saved["activation_count"] = stored.activation_count.into();
+        saved["last_activated_at"] = stored.last_activated_at.to_owned().into();
+    }
+
+    let mut status_msg = render_status(&settings.status_activated, STATUS_ACTIVATED, &[
+        ("preset", preset.name.to_owned())
+    ]);
+
+    if !busy_zones.is_empty() {
+        status_msg = format!("{}, ungrouped: {}", status_msg, busy_zones.join(", "));
+    }
+
+    {
+        let output_list = state.outputs.lock().unwrap();
+
+        note_safety_cap(&mut status_msg, &settings.safety_cap().engaged(preset, &output_ids, &output_list, state.last_volumes, &settings.output_aliases));
+
+        if preset.power_on_members {
+            let unwakeable = find_unwakeable_members(preset, &output_list).join(", ");
+
+            if !unwakeable.is_empty() {
+                status_msg = format!("{}, could not wake: {}", status_msg, unwakeable);
+            }
+        }
+    }
+
+    match activation {
+        Activation::Resumed(wait_missing) if !wait_missing.is_empty() => {
+            let names: Vec<String> = wait_missing
+                .iter()
+                .map(|output_id| saved_output_name(settings, output_id).to_owned())
+                .collect();
+
+            status_msg = format!("{}, missing: {}", status_msg, names.join(", "));
+        }
+        _ => ()
+    }
+
+    (status_msg, false)
+}
+
+// Logs everything needed to make sense of a support request, without changing any state
+fn dump_diagnostics(
+    presets: &[Preset],
+    outputs: &HashMap<String, Output>,
+    zones: &HashMap<String, Zone>,
+    matched_zone: Option<&MatchedZone>,
+    status_msg: &str
+) {
+    println!("Diagnostics: {} presets, {} outputs, {} zones", presets.len(), outputs.len(), zones.len());
+
+    for preset in presets {
+        println!("  Preset {}", serde_json::to_string(preset).unwrap_or_default());
+    }
+
+    for (output_id, output) in outputs {
+        println!("  Output {} \"{}\" in zone {}", output_id, output.display_name, output.zone_id);
+    }
+
+    for (zone_id, zone) in zones {
+        println!("  Zone {} \"{}\" ({}): {}", zone_id, zone.display_name, state_name(&zone.state), zone_members(zone).join(", "));
+    }
+
+    match matched_zone {
+        Some(matched) => println!("  Matched preset \"{}\" in zone {}", matched.preset_name, matched.zone_id),
+        None => println!("  No preset matched")
+    }
+
+    println!("  Last status: {}", status_msg);
+}
+
+fn startup_status(presets: &[Preset]) -> String {
+    match presets.len() {
+        0 => "No presets configured".to_owned(),
+        1 => "Loaded 1 preset, none active".to_owned(),
+        count => format!("Loaded {} presets, none active", count)
+    }
+}
+
+fn load_settings(key: &str) -> Option<GroupingSettings> {
+    let mut settings = serde_json::from_value::<GroupingSettings>(RoonApi::load_config(key)).ok()?;
+
+    settings.auto_match_enabled.get_or_insert(true);
+
+    Some(settings)
+}
+
+fn find_error(errors: &[(String, String)], setting: &str) -> Option<String> {
+    errors
+        .iter()
+        .find(|(key, _)| key == setting)
+        .map(|(_, err_msg)| err_msg.to_owned())
+}
+
+fn zone_members(zone: &Zone) -> Vec<&str> {
+    zone.outputs.iter().map(|output| output.output_id.as_str()).collect()
+}
+
+// The name an output had when the preset was saved, for outputs that are gone
+fn saved_output_name<'a>(settings: &'a GroupingSettings, output_id: &'a str) -> &'a str {
+    settings.selected
+        .and_then(|selected| settings.presets.get(selected))
+        .and_then(|preset| preset.output_names.get(output_id))
+        .map_or(output_id, |name| name.as_str())
+}
+
+fn make_zones_group(zones: &HashMap<String, Zone>) -> Widget {
+    let mut grouped: Vec<&Zone> = zones.values().filter(|zone| zone.outputs.len() > 1).collect();
+
+    grouped.sort_by(|a, b| a.display_name.cmp(&b.display_name));
+
+    let mut items: Vec<Widget> = grouped
+        .iter()
+        .map(|zone| {
+            let members: Vec<&str> = zone.outputs.iter().map(|output| output.display_name.as_str()).collect();
+
+            Widget::Label(Label {
+                title: zone.display_name.to_owned(),
+                subtitle: Some(members.join("\n"))
+            })
+        })
+        .collect();
+
+    if items.is_empty() {
+        items.push(Widget::Label(Label {
+            title: "No grouped zones".to_owned(),
+            subtitle: None
+        }));
+    }
+
+    Widget::Group(Group {
+        title: "Current Zones",
+        subtitle: Some("Live grouped zones and their outputs".to_owned()),
+        collapsable: true,
+        items
+    })
+}
+
+// Only depends on its arguments, the settings callbacks merely pass in the shared state
+pub(crate) fn make_layout(
+    settings: GroupingSettings,
+    outputs: &HashMap<String, Output>,
+    zones: &HashMap<String, Zone>
+) -> Layout<GroupingSettings> {
+    // Right after connecting the core may not have reported its outputs yet
+    if outputs.is_empty() {
+        let widgets = vec![Widget::Label(Label {
+            title: "Waiting for outputs from the core...".to_owned(),
+            subtitle: None
+        })];
+
+        return Layout {
+            settings,
+            widgets,
+            has_error: false
+        }
+    }
+
+    let mut errors = settings.validate(outputs);
+
+    errors.extend(settings.store_errors.iter().cloned());
+
+    let mut has_error = !errors.is_empty();
+    let selection = settings.selection();
+    let mut widgets = Vec::new();
+    let mut preset_list = vec![HashMap::from([ ("title", "(select preset)".into()), ("value", Value::Null) ])];
+    let filter = settings.filter.trim().to_lowercase();
+
+    if settings.advanced_mode {
+        widgets.push(Widget::Textbox(Textbox {
+            title: "Search",
+            subtitle: Some("Only list presets with a name containing this text".to_owned()),
+            setting: "filter"
+        }));
+    }
+
+    // Favorites are listed first, the indices stay the same
+    let mut indices: Vec<usize> = (0..settings.presets.len()).collect();
+
+    indices.sort_by_key(|index| !settings.presets[*index].favorite);
+
+    for index in indices {
+        let name = settings.presets[index].name.to_owned();
+        // The selected preset stays listed to keep the context
+        let is_listed = filter.is_empty()
+            || name.to_lowercase().contains(&filter)
+            || settings.selected == Some(index);
+
+        if name.len() > 0 && is_listed {
+            let title = if settings.presets[index].favorite { format!("\u{2605} {}", name) } else { name };
+
+            preset_list.push(HashMap::from([ ("title", title.into()), ("value", index.into()) ]));
+        }
+    }
+
+    preset_list.push(HashMap::from([ ("title", "New Preset".into()), ("value", settings.presets.len().into()) ]));
+
+    let selected = Widget::Dropdown(Dropdown {
+        title: "Preset",
+        subtitle: None,
+        values: preset_list,
+        setting: "selected"
+    });
+
+    widgets.push(selected);
+
+    if selection != PresetSelection::None {
+        if let PresetSelection::Existing(_) = selection {
+            let mut actions = Vec::new();
+
+            actions.push(HashMap::from([ ("title", "(select action)".into()), ("value", Value::Null) ]));
+            actions.push(HashMap::from([ ("title", "Activate".into()), ("value", (Action::Activate as usize).into()) ]));
+            actions.push(HashMap::from([ ("title", "Deactivate".into()), ("value", (Action::Deactivate as usize).into()) ]));
+            actions.push(HashMap::from([ ("title", "Edit".into()), ("value", (Action::Edit as usize).into()) ]));
+            actions.push(HashMap::from([ ("title", "Delete".into()), ("value", (Action::Delete as usize).into()) ]));
+
+            if let PresetSelection::Existing(index) = selection {
+                if settings.presets[index].favorite {
+                    actions.push(HashMap::from([ ("title", "Remove from Favorites".into()), ("value", (Action::Unfavorite as usize).into()) ]));
+                } else {
+                    actions.push(HashMap::from([ ("title", "Add to Favorites".into()), ("value", (Action::Favorite as usize).into()) ]));
+                }
+            }
+
+            if settings.advanced_mode {
+                actions.push(HashMap::from([ ("title", "Ungroup All".into()), ("value", (Action::UngroupAll as usize).into()) ]));
+                actions.push(HashMap::from([ ("title", "Toggle Mute".into()), ("value", (Action::ToggleMute as usize).into()) ]));
+
+                if let PresetSelection::Existing(index) = selection {
+                    if settings.presets[index].is_armed() {
+                        actions.push(HashMap::from([ ("title", "Disarm".into()), ("value", (Action::Disarm as usize).into()) ]));
+                    } else {
+                        actions.push(HashMap::from([ ("title", "Arm".into()), ("value", (Action::Arm as usize).into()) ]));
+                    }
+                }
+
+                if let VolumeType::Preset = settings.volume_type {
+                    actions.push(HashMap::from([ ("title", "Copy Volume to All".into()), ("value", (Action::CopyVolume as usize).into()) ]));
+                }
+
+                if !matches!(settings.volume_type, VolumeType::Untouched) {
+                    actions.push(HashMap::from([ ("title", "Apply Volumes Only".into()), ("value", (Action::ApplyVolumes as usize).into()) ]));
+                    actions.push(HashMap::from([ ("title", "Clear Volume Settings".into()), ("value", (Action::ResetVolumes as usize).into()) ]));
+                }
+
+                actions.push(HashMap::from([ ("title", "Find Duplicate Presets".into()), ("value", (Action::FindDuplicates as usize).into()) ]));
+                actions.push(HashMap::from([ ("title", "Dump Diagnostics".into()), ("value", (Action::DumpDiagnostics as usize).into()) ]));
+                actions.push(HashMap::from([ ("title", "Compact Presets".into()), ("value", (Action::CompactPresets as usize).into()) ]));
+
+                // Merging is only offered as a confirmation of the reported duplicates
+                if let Action::FindDuplicates = settings.action {
+                    if !find_duplicates(&settings.presets).is_empty() {
+                        actions.push(HashMap::from([ ("title", "Merge Duplicate Presets".into()), ("value", (Action::MergeDuplicates as usize).into()) ]));
+                    }
+                }
+
+                if settings.active_profile.is_some() {
+                    actions.push(HashMap::from([ ("title", "Remove Volume Profile".into()), ("value", (Action::RemoveProfile as usize).into()) ]));
+                }
+
+                actions.push(HashMap::from([ ("title", "Replace Output".into()), ("value", (Action::ReplaceOutput as usize).into()) ]));
+                actions.push(HashMap::from([ ("title", "Activate Multiple".into()), ("value", (Action::ActivateBatch as usize).into()) ]));
+                actions.push(HashMap::from([ ("title", "Set Volume Levels for All".into()), ("value", (Action::SetVolumeTypeAll as usize).into()) ]));
+
+                // Changing all presets is only offered as a confirmation of the previewed change
+                if let Action::SetVolumeTypeAll = settings.action {
+                    actions.push(HashMap::from([ ("title", "Confirm Volume Levels for All".into()), ("value", (Action::ConfirmVolumeTypeAll as usize).into()) ]));
+                }
+            }
+
+            let action = Widget::Dropdown(Dropdown {
+                title: "Action",
+                subtitle: None,
+                values: actions,
+                setting: "action"
+            });
+
+            widgets.push(action);
+
+            if let PresetSelection::Existing(index) = selection {
+                let preset = &settings.presets[index];
+
+                if preset.activation_count > 0 {
+                    let subtitle = match &preset.last_activated_at {
+                        Some(last_activated_at) => format!("Used {} times, last: {}", preset.activation_count, last_activated_at),
+                        None => format!("Used {} times", preset.activation_count)
+                    };
+
+                    widgets.push(Widget::Label(Label {
+                        title: "Usage".to_owned(),
+                        subtitle: Some(subtitle)
+                    }));
+                }
+
+                let live_zones: Vec<Zone> = zones.values().cloned().collect();
+
+                if let Some((_, zone)) = match_preset(std::slice::from_ref(preset), &live_zones) {
+                    widgets.push(Widget::Label(Label {
+                        title: "Active".to_owned(),
+                        subtitle: Some(format!("{}, master: {}", zone.display_name, volume_master_name(zone).unwrap_or("unknown")))
+                    }));
+                }
+
+                if !preset.is_armed() {
+                    widgets.push(Widget::Label(Label {
+                        title: "Disarmed".to_owned(),
+                        subtitle: Some("Automatic actions of this preset are suspended".to_owned())
+                    }));
+                }
+
+                let overlaps = find_overlaps(&settings.presets, index, outputs, &settings.output_aliases);
+
+                if !overlaps.is_empty() {
+                    widgets.push(Widget::Label(Label {
+                        title: "Shared Outputs".to_owned(),
+                        subtitle: Some(overlaps.join("\n"))
+                    }));
+                }
+            }
+        }
+
+        match settings.action {
+            Action::Edit => {
+                let name = Widget::Textbox(Textbox {
+                    title: "Name",
+                    subtitle: None,
+                    setting: "name"
+                });
+                let auto_name = Widget::Dropdown(Dropdown {
+                    title: "Naming",
+                    subtitle: None,
+                    values: vec![
+                        HashMap::from([ ("title", "Custom Name".into()), ("value", false.into()) ]),
+                        HashMap::from([ ("title", "Name After Outputs".into()), ("value", true.into()) ])
+                    ],
+                    setting: "auto_name"
+                });
+                let mut items = if settings.advanced_mode {
+                    vec![name, auto_name]
+                } else {
+                    vec![name]
+                };
+
+                if let Some(err_msg) = find_error(&errors, "name") {
+                    items.insert(1, Widget::Label(Label {
+                        title: err_msg,
+                        subtitle: None
+                    }));
+                }
+
+                let mut edit_group = Widget::Group(Group {
+                    title: "Preset Editor",
+                    subtitle: None,
+                    collapsable: true,
+                    items
+                });
+
+                if settings.name.len() > 0 || settings.auto_name {
+                    if let Widget::Group(edit_group) = &mut edit_group {
+                        let mut values = vec![HashMap::from(
+                            [ ("title", "(select output)".into()), ("value", Value::Null) ]
+                        )];
+
+                        for (output_id, output) in outputs {
+                            // Outputs that can't group with anything make no sense as primary
+                            let is_selected = settings.primary_output_id.as_ref() == Some(output_id);
+
+                            if is_groupable(output) || is_selected {
+                                values.push(HashMap::from(
+                                    [ ("title", settings.output_name(output).to_owned().into()), ("value", output_id.to_owned().into()) ]
+                                ));
+                            }
+                        }
+
+                        if let Some(primary_output_id) = settings.primary_output_id.as_ref().filter(|output_id| !outputs.contains_key(*output_id)) {
+                            let name = format!("{} (unavailable)", saved_output_name(&settings, primary_output_id));
+
+                            values.push(HashMap::from([ ("title", name.into()), ("value", primary_output_id.to_owned().into()) ]));
+                        }
+
+                        let output = Widget::Dropdown(Dropdown {
+                            title: "Primary Output",
+                            subtitle: None,
+                            values,
+                            setting: "primary_output_id"
+                        });
+
+                        edit_group.items.push(output);
+
+                        if let Some(err_msg) = find_error(&errors, "primary_output_id") {
+                            edit_group.items.push(Widget::Label(Label {
+                                title: err_msg,
+                                subtitle: None
+                            }));
+                        }
+
+                        if let Some(primary_output_id) = &settings.primary_output_id {
+                            if let Some(output) = outputs.get(primary_output_id) {
+                                let mut values = vec![HashMap::from([ ("title", "(select output)".into()), ("value", Value::Null) ])];
+
+                                for output_id in &output.can_group_with_output_ids {
+                                    if *output_id != *primary_output_id && !settings.output_ids.contains(output_id) {
+                                        if let Some(output) = outputs.get(output_id) {
+                                            let name = settings.output_name(output).to_owned();
+
+                                            values.push(HashMap::from([ ("title", name.into()), ("value", output_id.to_owned().into()) ]));
+                                        }
+                                    }
+                                }
+
+                                if !is_groupable(output) {
+                                    edit_group.items.push(Widget::Label(Label {
+                                        title: "This output cannot be grouped with others; pick a different primary".to_owned(),
+                                        subtitle: None
+                                    }));
+                                } else if values.len() > 1 {
+                                    edit_group.items.push(Widget::Dropdown(Dropdown {
+                                        title: "Group With",
+                                        subtitle: None,
+                                        values,
+                                        setting: "add"
+                                    }));
+                                } else {
+                                    edit_group.items.push(Widget::Label(Label {
+                                        title: "All compatible outputs added".to_owned(),
+                                        subtitle: None
+                                    }));
+                                }
+
+                                if let Some(err_msg) = find_error(&errors, "add") {
+                                    edit_group.items.push(Widget::Label(Label {
+                                        title: err_msg,
+                                        subtitle: None
+                                    }));
+                                }
+
+                                // Power user settings
+                                if settings.advanced_mode {
+                                    if settings.output_ids.len() > 1 {
+                                        let mut values = vec![
+                                            HashMap::from([ ("title", "(primary output)".into()), ("value", Value::Null) ])
+                                        ];
+
+                                        for output_id in &settings.output_ids {
+                                            if let Some(output) = outputs.get(output_id) {
+                                                let name = settings.output_name(output).to_owned();
+
+                                                values.push(HashMap::from([ ("title", name.into()), ("value", output_id.to_owned().into()) ]));
+                                            }
+                                        }
+
+                                        edit_group.items.push(Widget::Dropdown(Dropdown {
+                                            title: "Volume Master",
+                                            subtitle: Some("Leads the group, its zone and queue are kept when grouping".to_owned()),
+                                            values,
+                                            setting: "volume_master"
+                                        }));
+                                        edit_group.items.push(Widget::Integer(Integer {
+                                            title: "Stagger Delay",
+                                            subtitle: Some("Delay in ms between adding each output, leave empty to group at once".to_owned()),
+                                            min: "0".to_owned(),
+                                            max: STAGGER_MS_MAX.to_string(),
+                                            setting: "stagger_ms",
+                                            error: find_error(&errors, "stagger_ms")
+                                        }));
+                                    }
+
+                                    edit_group.items.push(Widget::Integer(Integer {
+                                        title: "Wait for Outputs",
+                                        subtitle: Some("Time in ms to wait for offline members to appear, leave empty to group the available outputs".to_owned()),
+                                        min: "0".to_owned(),
+                                        max: WAIT_FOR_OUTPUTS_MS_MAX.to_string(),
+                                        setting: "wait_for_outputs_ms",
+                                        error: find_error(&errors, "wait_for_outputs_ms")
+                                    }));
+
+                                    edit_group.items.push(Widget::Integer(Integer {
+                                        title: "Cooldown",
+                                        subtitle: Some("Time in s after an activation during which another one is ignored, leave empty to allow any time".to_owned()),
+                                        min: "0".to_owned(),
+                                        max: COOLDOWN_SECONDS_MAX.to_string(),
+                                        setting: "cooldown_seconds",
+                                        error: find_error(&errors, "cooldown_seconds")
+                                    }));
+
+                                    if !settings.room_sets.is_empty() {
+                                        let mut values = vec![
+                                            HashMap::from([ ("title", "(none)".into()), ("value", Value::Null) ])
+                                        ];
+
+                                        for room_set in &settings.room_sets {
+                                            values.push(HashMap::from([ ("title", room_set.name.to_owned().into()), ("value", room_set.name.to_owned().into()) ]));
+                                        }
+
+                                        edit_group.items.push(Widget::Dropdown(Dropdown {
+                                            title: "Room Set",
+                                            subtitle: Some("Its outputs are grouped in addition to the ones above".to_owned()),
+                                            values,
+                                            setting: "room_set"
+                                        }));
+                                    }
+
+                                    edit_group.items.push(Widget::Textbox(Textbox {
+                                        title: "Zone Label",
+                                        subtitle: Some("Name used for the grouped zone in status messages".to_owned()),
+                                        setting: "zone_label"
+                                    }));
+
+                                    edit_group.items.push(Widget::Integer(Integer {
+                                        title: "Priority",
+                                        subtitle: Some("Decides between presets that match or activate at the same time, leave empty for 0".to_owned()),
+                                        min: PRIORITY_MIN.to_string(),
+                                        max: PRIORITY_MAX.to_string(),
+                                        setting: "priority",
+                                        error: find_error(&errors, "priority")
+                                    }));
+
+                                    edit_group.items.push(Widget::Textbox(Textbox {
+                                        title: "Play Radio Station",
+                                        subtitle: Some("Name of a Live Radio station to start after grouping".to_owned()),
+                                        setting: "play_item"
+                                    }));
+
+                                    edit_group.items.push(Widget::Dropdown(Dropdown {
+                                        title: "Other Presets",
+                                        subtitle: None,
+                                        values: vec![
+                                            HashMap::from([ ("title", "Keep Active".into()), ("value", false.into()) ]),
+                                            HashMap::from([ ("title", "Deactivate (Exclusive)".into()), ("value", true.into()) ])
+                                        ],
+                                        setting: "exclusive"
+                                    }));
+
+                                    edit_group.items.push(Widget::Dropdown(Dropdown {
+                                        title: "Members in Standby",
+                                        subtitle: Some("Outputs that drop out of Roon while sleeping can't be woken".to_owned()),
+                                        values: vec![
+                                            HashMap::from([ ("title", "Leave in Standby".into()), ("value", false.into()) ]),
+                                            HashMap::from([ ("title", "Power On First".into()), ("value", true.into()) ])
+                                        ],
+                                        setting: "power_on_members"
+                                    }));
+
+                                    edit_group.items.push(Widget::Dropdown(Dropdown {
+                                        title: "Volume While Active",
+                                        subtitle: Some("Following keeps the other members at the level of the primary output, in % of their range".to_owned()),
+                                        values: vec![
+                                            HashMap::from([ ("title", "Independent".into()), ("value", false.into()) ]),
+                                            HashMap::from([ ("title", "Follow Primary Output".into()), ("value", true.into()) ])
+                                        ],
+                                        setting: "mirror"
+                                    }));
+
+                                    let values = vec![
+                                        HashMap::from([ ("title", "(select volume control)".into()), ("value", Value::Null) ]),
+                                        HashMap::from([ ("title", "Untouched".into()), ("value", (VolumeType::Untouched as usize).into()) ]),
+                                        HashMap::from([ ("title", "Last Used".into()), ("value", (VolumeType::LastUsed as usize).into()) ]),
+                                        HashMap::from([ ("title", "Preset".into()), ("value", (VolumeType::Preset as usize).into()) ])
+                                    ];
+
+                                    edit_group.items.push(Widget::Dropdown(Dropdown {
+                                        title: "Volume Levels",
+                                        subtitle: None,
+                                        values,
+                                        setting: "volume_type"
+                                    }));
+
+                                    if !matches!(settings.volume_type, VolumeType::Untouched) {
+                                        edit_group.items.push(Widget::Integer(Integer {
+                                            title: "Volume Delay",
+                                            subtitle: Some("Delay in ms after grouping before volumes are set, leave empty to set them first".to_owned()),
+                                            min: "0".to_owned(),
+                                            max: VOLUME_DELAY_MS_MAX.to_string(),
+                                            setting: "volume_delay_ms",
+                                            error: find_error(&errors, "volume_delay_ms")
+                                        }));
+                                        edit_group.items.push(Widget::Integer(Integer {
+                                            title: "Fade Time",
+                                            subtitle: Some("Time in ms to ramp each output to its volume, leave empty to set it at once".to_owned()),
+                                            min: "0".to_owned(),
+                                            max: FADE_MS_MAX.to_string(),
+                                            setting: "fade_ms",
+                                            error: find_error(&errors, "fade_ms")
+                                        }));
+                                        edit_group.items.push(Widget::Integer(Integer {
+                                            title: "Master Scale",
+                                            subtitle: Some("Scales all volumes in % of their stored level, leave empty for 100%".to_owned()),
+                                            min: MASTER_SCALE_MIN.to_string(),
+                                            max: MASTER_SCALE_MAX.to_string(),
+                                            setting: "master_scale",
+                                            error: find_error(&errors, "master_scale")
+                                        }));
+                                        edit_group.items.push(Widget::Dropdown(Dropdown {
+                                            title: "Manual Grouping",
+                                            subtitle: Some("Volumes to use when the group is formed in the Roon app".to_owned()),
+                                            values: vec![
+                                                HashMap::from([ ("title", "Leave Untouched".into()), ("value", false.into()) ]),
+                                                HashMap::from([ ("title", "Apply Volumes".into()), ("value", true.into()) ])
+                                            ],
+                                            setting: "apply_volume_on_match"
+                                        }));
+                                    }
+
+                                    if let VolumeType::Preset = settings.volume_type {
+                                        let profiles: Vec<String> = settings.selected
+                                            .and_then(|selected| settings.presets.get(selected))
+                                            .map(|preset| {
+                                                let mut profiles: Vec<&String> = preset.volume_profiles.keys().collect();
+
+                                                profiles.sort();
+                                                profiles.iter().map(|profile| profile.to_string()).collect()
+                                            })
+                                            .unwrap_or_else(Vec::new);
+
+                                        if !profiles.is_empty() {
+                                            let mut values = vec![
+                                                HashMap::from([ ("title", "(stored volumes)".into()), ("value", Value::Null) ])
+                                            ];
+
+                                            for profile in profiles {
+                                                values.push(HashMap::from([ ("title", profile.to_owned().into()), ("value", profile.into()) ]));
+                                            }
+
+                                            edit_group.items.push(Widget::Dropdown(Dropdown {
+                                                title: "Volume Profile",
+                                                subtitle: Some("Volumes applied on activation".to_owned()),
+                                                values,
+                                                setting: "active_profile"
+                                            }));
+                                        }
+
+                                        edit_group.items.push(Widget::Textbox(Textbox {
+                                            title: "Save Volumes as Profile",
+                                            subtitle: Some("Stores the volumes below under this name, e.g. \"night\"".to_owned()),
+                                            setting: "new_profile"
+                                        }));
+                                        edit_group.items.push(Widget::Dropdown(Dropdown {
+                                            title: "Volume Units",
+                                            subtitle: None,
+                                            values: vec![
+                                                HashMap::from([ ("title", "Native".into()), ("value", false.into()) ]),
+                                                HashMap::from([ ("title", "Percentage".into()), ("value", true.into()) ])
+                                            ],
+                                            setting: "volume_as_percent"
+                                        }));
+
+                                        let mut values = vec![
+                                            HashMap::from([ ("title", "(select output)".into()), ("value", Value::Null) ])
+                                        ];
+
+                                        for output_id in &settings.output_ids {
+                                            if let Some(output) = outputs.get(output_id) {
+                                                let name = match output.volume.as_ref() {
+                                                    Some(volume) => format!(
+                                                        "{} ({}{})",
+                                                        settings.output_name(output),
+                                                        live_volume_level(volume),
+                                                        volume_unit(volume)
+                                                    ),
+                                                    None => settings.output_name(output).to_owned()
+                                                };
+
+                                                values.push(HashMap::from([ ("title", name.into()), ("value", output_id.to_owned().into()) ]));
+                                            }
+                                        }
+
+                                        edit_group.items.push(Widget::Dropdown(Dropdown {
+                                            title: "Output",
+                                            subtitle: None,
+                                            values,
+                                            setting: "volume_output_id"
+                                        }));
+
+                                        if let Some(err_msg) = find_error(&errors, "volume_output_id") {
+                                            edit_group.items.push(Widget::Label(Label {
+                                                title: err_msg,
+                                                subtitle: None
+                                            }));
+                                        }
+
+                                        if settings.volume_output_cleared {
+                                            edit_group.items.push(Widget::Label(Label {
+                                                title: "The selected output is no longer part of this preset".to_owned(),
+                                                subtitle: Some("Select an output to set its volume".to_owned())
+                                            }));
+                                        }
+
+                                        if let Some(output_id) = &settings.volume_output_id {
+                                            if let Some(volume) = outputs.get(output_id).and_then(|output| output.volume.as_ref()) {
+                                                let unit = volume_unit(volume);
+                                                let step = volume_step(volume);
+                                                let subtitle = match (unit.is_empty(), step == 1.0) {
+                                                    _ if settings.volume_as_percent => Some(format!(
+                                                        "Volume level in % of {}{} to {}{}",
+                                                        volume.hard_limit_min,
+                                                        unit,
+                                                        volume.hard_limit_max,
+                                                        unit
+                                                    )),
+                                                    (true, true) => None,
+                                                    (false, true) => Some(format!("Volume level in {}", unit)),
+                                                    (_, false) => Some(format!("Volume level in steps of {}{}", step, unit))
+                                                };
+                                                let (min, max) = if settings.volume_as_percent {
+                                                    ("0".to_owned(), "100".to_owned())
+                                                } else {
+                                                    (volume.hard_limit_min.to_string(), volume.hard_limit_max.to_string())
+                                                };
+                                                let mut volume_level = Integer {
+                                                    title: "Output Volume",
+                                                    subtitle,
+                                                    min,
+                                                    max,
+                                                    setting: "volume_level",
+                                                    error: find_error(&errors, "volume_level")
+                                                };
+
+                                                if volume_level.error.is_none() {
+                                                    if let Ok(true) = volume_level.out_of_range(&settings.volume_level) {
+                                                        let err_msg = format!("Volume level should be between {} and {}", volume_level.min, volume_level.max);
+
+                                                        volume_level.error = Some(err_msg);
+                                                    }
+                                                }
+
+                                                has_error |= volume_level.error.is_some();
+
+                                                edit_group.items.push(Widget::Integer(volume_level));
+                                            }
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+
+                widgets.push(edit_group);
+            }
+            Action::ReplaceOutput => {
+                let mut from_values = vec![HashMap::from([ ("title", "(select output)".into()), ("value", Value::Null) ])];
+
+                for output_id in &settings.output_ids {
+                    let name = match outputs.get(output_id) {
+                        Some(output) => settings.output_name(output).to_owned(),
+                        None => format!("{} (unavailable)", saved_output_name(&settings, output_id))
+                    };
+
+                    from_values.push(HashMap::from([ ("title", name.into()), ("value", output_id.to_owned().into()) ]));
+                }
+
+                let mut to_values = vec![HashMap::from([ ("title", "(select output)".into()), ("value", Value::Null) ])];
+
+                for (output_id, output) in outputs {
+                    if !settings.output_ids.contains(output_id) && is_groupable(output) {
+                        to_values.push(HashMap::from([ ("title", settings.output_name(output).to_owned().into()), ("value", output_id.to_owned().into()) ]));
+                    }
+                }
+
+                widgets.push(Widget::Dropdown(Dropdown {
+                    title: "Replace",
+                    subtitle: None,
+                    values: from_values,
+                    setting: "replace_from"
+                }));
+                widgets.push(Widget::Dropdown(Dropdown {
+                    title: "With",
+                    subtitle: None,
+                    values: to_values,
+                    setting: "replace_to"
+                }));
+
+                if let Some(err_msg) = find_error(&errors, "replace_to") {
+                    widgets.push(Widget::Label(Label {
+                        title: err_msg,
+                        subtitle: None
+                    }));
+                }
+            }
+            Action::ActivateBatch => {
+                let mut add_values = vec![HashMap::from([ ("title", "(select preset)".into()), ("value", Value::Null) ])];
+                let mut remove_values = add_values.to_owned();
+
+                for (index, preset) in settings.presets.iter().enumerate() {
+                    let value = HashMap::from([ ("title", preset.name.to_owned().into()), ("value", index.into()) ]);
+
+                    if settings.batch.contains(&index) {
+                        remove_values.push(value);
+                    } else {
+                        add_values.push(value);
+                    }
+                }
+
+                let names: Vec<&str> = batch_order(&settings.presets, &settings.batch)
+                    .iter()
+                    .map(|index| settings.presets[*index].name.as_str())
+                    .collect();
+
+                widgets.push(Widget::Dropdown(Dropdown {
+                    title: "Add Preset",
+                    subtitle: None,
+                    values: add_values,
+                    setting: "batch_add"
+                }));
+                widgets.push(Widget::Dropdown(Dropdown {
+                    title: "Remove Preset",
+                    subtitle: None,
+                    values: remove_values,
+                    setting: "batch_remove"
+                }));
+                widgets.push(Widget::Label(Label {
+                    title: "Presets to Activate".to_owned(),
+                    subtitle: Some(if names.is_empty() { "(none)".to_owned() } else { names.join("\n") })
+                }));
+            }
+            Action::SetVolumeTypeAll => {
+                let values = vec![
+                    HashMap::from([ ("title", "Leave Untouched".into()), ("value", (VolumeType::Untouched as usize).into()) ]),
+                    HashMap::from([ ("title", "Last Used".into()), ("value", (VolumeType::LastUsed as usize).into()) ]),
+                    HashMap::from([ ("title", "Preset".into()), ("value", (VolumeType::Preset as usize).into()) ])
+                ];
+                let count = count_volume_type_changes(&settings.presets, &settings.volume_type);
+
+                widgets.push(Widget::Dropdown(Dropdown {
+                    title: "Volume Levels",
+                    subtitle: None,
+                    values,
+                    setting: "volume_type"
+                }));
+                widgets.push(Widget::Label(Label {
+                    title: format!("{} of {} presets will change", count, settings.presets.len()),
+                    subtitle: Some("Select Confirm Volume Levels for All to apply".to_owned())
+                }));
+            }
+            Action::Activate if settings.advanced_mode => {
+                let values = vec![
+                    HashMap::from([ ("title", "Leave Untouched".into()), ("value", false.into()) ]),
+                    HashMap::from([ ("title", "Re-apply Volumes".into()), ("value", true.into()) ])
+                ];
+
+                widgets.push(Widget::Dropdown(Dropdown {
+                    title: "When Already Active",
+                    subtitle: None,
+                    values,
+                    setting: "force"
+                }));
+            }
+            _ => ()
+        }
+
+        if let PresetSelection::Existing(index) = selection {
+            if settings.presets[index].output_ids.is_empty() {
+                widgets.push(Widget::Label(Label {
+                    title: "Preset has no outputs, please rebuild".to_owned(),
+                    subtitle: None
+                }));
+            }
+        }
+
+        if settings.primary_output_id.is_none() && !settings.output_ids.is_empty() {
+            widgets.push(Widget::Label(Label {
+                title: "All members offline".to_owned(),
+                subtitle: Some("None of the outputs of this preset are currently available".to_owned())
+            }));
+        }
+
+        let primary_output = settings.primary_output_id
+            .as_ref()
+            .and_then(|primary_output_id| Some((primary_output_id, outputs.get(primary_output_id)?)));
+
+        if let Some((primary_output_id, primary_output)) = primary_output {
+            let name = settings.output_name(primary_output).to_owned();
+            let mut subtitle = String::from("Grouped with:");
+
+            for output_id in &settings.output_ids {
+                if output_id == primary_output_id {
+                    continue;
+                }
+
+                subtitle.push('\n');
+
+                match outputs.get(output_id) {
+                    Some(sec_output) => subtitle.push_str(settings.output_name(sec_output)),
+                    None => subtitle.push_str(&format!("{} (unavailable)", saved_output_name(&settings, output_id)))
+                }
+            }
+
+            widgets.push(Widget::Label(Label {
+                title: name.to_owned(),
+                subtitle: Some(subtitle)
+            }));
+        }
+    }
+
+    if settings.advanced_mode {
+        let values = vec![
+            HashMap::from([ ("title", "Keep Grouped".into()), ("value", false.into()) ]),
+            HashMap::from([ ("title", "Deactivate Preset".into()), ("value", true.into()) ])
+        ];
+
+        widgets.push(Widget::Dropdown(Dropdown {
+            title: "On Exit",
+            subtitle: None,
+            values,
+            setting: "deactivate_on_exit"
+        }));
+
+        widgets.push(Widget::Dropdown(Dropdown {
+            title: "Outputs in Other Groups",
+            subtitle: Some("What to do when a preset to activate has members grouped elsewhere".to_owned()),
+            values: vec![
+                HashMap::from([ ("title", "Take Over".into()), ("value", false.into()) ]),
+                HashMap::from([ ("title", "Refuse Activation".into()), ("value", true.into()) ])
+            ],
+            setting: "refuse_busy_outputs"
+        }));
+
+        widgets.push(Widget::Integer(Integer {
+            title: "Status Grace Period",
+            subtitle: Some("Time in ms a removed zone may take to return before no preset is reported active, leave empty to report at once".to_owned()),
+            min: "0".to_owned(),
+            max: STATUS_GRACE_MS_MAX.to_string(),
+            setting: "status_grace_ms",
+            error: find_error(&errors, "status_grace_ms")
+        }));
+
+        widgets.push(Widget::Integer(Integer {
+            title: "Safety Minimum",
+            subtitle: Some("Lowest volume ever set in % of the range of an output, leave empty for no minimum".to_owned()),
+            min: "0".to_owned(),
+            max: "100".to_owned(),
+            setting: "safety_min",
+            error: find_error(&errors, "safety_min")
+        }));
+
+        widgets.push(Widget::Integer(Integer {
+            title: "Safety Maximum",
+            subtitle: Some("Highest volume ever set in % of the range of an output, leave empty for no maximum".to_owned()),
+            min: "0".to_owned(),
+            max: "100".to_owned(),
+            setting: "safety_max",
+            error: find_error(&errors, "safety_max")
+        }));
+
+        widgets.push(Widget::Dropdown(Dropdown {
+            title: "Automatic Matching",
+            subtitle: Some("Reports which preset is active based on the current zones".to_owned()),
+            values: vec![
+                HashMap::from([ ("title", "Enabled".into()), ("value", true.into()) ]),
+                HashMap::from([ ("title", "Disabled".into()), ("value", false.into()) ])
+            ],
+            setting: "auto_match_enabled"
+        }));
+
+        let values = vec![
+            HashMap::from([ ("title", "By ID".into()), ("value", false.into()) ]),
+            HashMap::from([ ("title", "By ID, Fall Back to Name".into()), ("value", true.into()) ])
+        ];
+
+        widgets.push(Widget::Dropdown(Dropdown {
+            title: "Output Matching",
+            subtitle: Some("Names can collide, ambiguous names are never resolved".to_owned()),
+            values,
+            setting: "match_by_name_fallback"
+        }));
+
+        let templates = [
+            ("Activated", "status_activated", STATUS_ACTIVATED),
+            ("Deactivated", "status_deactivated", STATUS_DEACTIVATED),
+            ("Zone Matched", "status_matched", STATUS_MATCHED)
+        ];
+        let mut items = Vec::new();
+
+        for (title, setting, default) in templates {
+            items.push(Widget::Textbox(Textbox {
+                title,
+                subtitle: Some(format!("Leave empty for: {}", default)),
+                setting
+            }));
+
+            if let Some(err_msg) = find_error(&errors, setting) {
+                items.push(Widget::Label(Label {
+                    title: err_msg,
+                    subtitle: None
+                }));
+            }
+        }
+
+        widgets.push(Widget::Group(Group {
+            title: "Status Messages",
+            subtitle: None,
+            collapsable: true,
+            items
+        }));
+
+        widgets.push(make_aliases_group(&settings, outputs));
+        widgets.push(make_room_sets_group(&settings, outputs));
+        widgets.push(make_zones_group(zones));
+
+        let near_misses = find_near_misses(&resolve_room_sets(&settings.presets, &settings.room_sets), zones.values(), &settings.output_aliases);
+
+        if !near_misses.is_empty() {
+            widgets.push(Widget::Label(Label {
+                title: "Nearly Matching Zones".to_owned(),
+                subtitle: Some(near_misses.join("\n"))
+            }));
+        }
+    }
+
+    widgets.push(Widget::Dropdown(Dropdown {
+        title: "Mode",
+        subtitle: None,
+        values: vec![
+            HashMap::from([ ("title", "Simple".into()), ("value", false.into()) ]),
+            HashMap::from([ ("title", "Advanced".into()), ("value", true.into()) ])
+        ],
+        setting: "advanced_mode"
+    }));
+
+    Layout {
+        settings,
+        widgets,
+        has_error
+    }
+}
+
+// The HashMaps in the settings serialize in any order, hash object keys sorted to get a stable key
+fn hash_sorted(value: &Value, hasher: &mut DefaultHasher) {
+    match value {
+        Value::Object(map) => {
+            let mut keys: Vec<&String> = map.keys().collect();
+
+            keys.sort();
+            map.len().hash(hasher);
+
+            for key in keys {
+                key.hash(hasher);
+                hash_sorted(&map[key], hasher);
+            }
+        }
+        Value::Array(values) => {
+            values.len().hash(hasher);
+
+            for value in values {
+                hash_sorted(value, hasher);
+            }
+        }
+        _ => value.to_string().hash(hasher)
+    }
+}
+
+fn save_last_volumes(last_volumes: &HashMap<String, f32>) {
+    // Losing them only affects the next Last Used activation, that is no reason to stop
+    if let Err(err) = RoonApi::save_config("last_volumes", json!(last_volumes)) {
+        println!("Failed to save the last used volumes: {}", err);
+    }
+}
+
+fn cached_layout(
+    cache: &mut Option<(u64, Layout<GroupingSettings>)>,
+    settings: GroupingSettings,
+    outputs: &HashMap<String, Output>,
+    zones: &HashMap<String, Zone>
+) -> Layout<GroupingSettings> {
+    // The cache is invalidated on output and grouping changes, the settings are part of the key
+    let mut hasher = DefaultHasher::new();
+
+    hash_sorted(&serde_json::to_value(&settings).unwrap_or_default(), &mut hasher);
+
+    // Renames don't regroup anything, yet the layout shows the names
+    let mut names: Vec<(&str, &str)> = outputs
+        .values()
+        .map(|output| (output.output_id.as_str(), output.display_name.as_str()))
+        .chain(zones.values().map(|zone| (zone.zone_id.as_str(), zone.display_name.as_str())))
+        .collect();
+
+    names.sort();
+    names.hash(&mut hasher);
+
+    let key = hasher.finish();
+
+    if let Some((cached_key, layout)) = cache.as_ref() {
+        if *cached_key == key {
+            return layout.clone()
+        }
+    }
+
+    let layout = make_layout(settings, outputs, zones);
+
+    *cache = Some((key, layout.clone()));
+
+    layout
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() {
+    let mut roon = RoonApi::new(info!("com.theappgineer", "Zone Presets"));
+    let mut provided: HashMap<String, Svc> = HashMap::new();
+    let output_list = Arc::new(Mutex::new(HashMap::new()));
+    let zone_list = Arc::new(Mutex::new(HashMap::new()));
+    let status_message = Arc::new(Mutex::new(String::new()));
+    let status_changed = Arc::new(Mutex::new(SystemTime::now()));
+    let last_selected = Arc::new(Mutex::new((None, None)));
+    // Replaced by the settings of the core once it is found
+    let settings = load_settings(LEGACY_SETTINGS_KEY).unwrap_or_default();
+    let saved_settings = Arc::new(Mutex::new(settings));
+
+    let layout_cache = Arc::new(Mutex::new(None));
+
+    let output_list_clone = output_list.clone();
+    let zone_list_clone = zone_list.clone();
+    let last_selected_clone = last_selected.clone();
+    let saved_settings_clone = saved_settings.clone();
+    let layout_cache_clone = layout_cache.clone();
+    let get_settings_cb = move |cb: fn(Layout<GroupingSettings>) -> Vec<RespProps>| -> Vec<RespProps> {
+        let output_list = output_list_clone.lock().unwrap();
+        let mut last_selected = last_selected_clone.lock().unwrap();
+        let saved_settings = saved_settings_clone.lock().unwrap();
+        let zone_list = zone_list_clone.lock().unwrap();
+        let mut layout_cache = layout_cache_clone.lock().unwrap();
+
+        *last_selected = (saved_settings.selected, saved_settings.volume_output_id.to_owned());
+
+        cb(cached_layout(&mut layout_cache, saved_settings.to_owned(), &output_list, &zone_list))
+    };
+
+    let output_list_clone = output_list.clone();
+    let zone_list_clone = zone_list.clone();
+    let layout_cache_clone = layout_cache.clone();
+    let save_settings_cb = move |is_dry_run: bool, mut settings: GroupingSettings| -> Vec<RespProps> {
+        let output_list = output_list_clone.lock().unwrap();
+        let mut last_selected = last_selected.lock().unwrap();
+        let zone_list = zone_list_clone.lock().unwrap();
+        let mut layout_cache = layout_cache_clone.lock().unwrap();
+        let mut resp_props: Vec<RespProps> = Vec::new();
+
+        if settings.selected.map_or(false, |selected| selected > settings.presets.len()) {
+            settings.selected = None;
+        }
+
+        if let Action::Delete = settings.action {
+            if let PresetSelection::Existing(index) = settings.selection() {
+                settings.presets.remove(index);
+                settings.selected = None;
+                settings.batch.clear();
+            }
+        }
+
+        if let Action::CopyVolume = settings.action {
+            copy_volume(&mut settings, &output_list);
+        }
+
+        if let Action::RemoveProfile = settings.action {
+            remove_profile(&mut settings);
+        }
+
+        if let Action::MergeDuplicates = settings.action {
+            merge_duplicates(&mut settings);
+        }
+
+        if let Action::ReplaceOutput = settings.action {
+            replace_output(&mut settings, &output_list);
+        }
+
+        if let Action::CompactPresets = settings.action {
+            compact_presets(&mut settings);
+        }
+
+        if let Action::ConfirmVolumeTypeAll = settings.action {
+            set_volume_type_all(&mut settings);
+        }
+
+        if let Action::ResetVolumes = settings.action {
+            reset_volumes(&mut settings);
+        }
+
+        if let Action::Arm | Action::Disarm = settings.action {
+            let is_armed = matches!(settings.action, Action::Arm);
+
+            set_armed(&mut settings, is_armed);
+        }
+
+        if let Action::Favorite | Action::Unfavorite = settings.action {
+            let is_favorite = matches!(settings.action, Action::Favorite);
+
+            set_favorite(&mut settings, is_favorite);
+        }
+
+        let selected_pair = (settings.selected, settings.volume_output_id.to_owned());
+
+        if selected_pair != *last_selected {
+            load_preset(&mut settings, &output_list);
+
+            settings.store_errors.clear();
+            *last_selected = selected_pair;
+        } else {
+            // A switch of profile shows the level stored in it, the level shown before belongs to the previous one
+            let is_profile_switched = settings.selected
+                .and_then(|selected| settings.presets.get(selected))
+                .map_or(false, |preset| preset.active_profile != settings.active_profile);
+
+            apply_auto_name(&mut settings, &output_list);
+            // The volume type picked for all presets is no edit of the selected one
+            let results = if let Action::SetVolumeTypeAll = settings.action {
+                Vec::new()
+            } else if is_profile_switched {
+                let result = store_preset(&mut settings, &output_list);
+
+                load_volume_level(&mut settings, &output_list);
+                vec![result]
+            } else {
+                vec![store_preset(&mut settings, &output_list), store_volume(&mut settings, &output_list)]
+            };
+
+            // Only the editor shows why nothing was stored, other actions leave the preset as it was
+            let is_editing = matches!(settings.action, Action::Edit);
+
+            settings.store_errors = results
+                .iter()
+                .filter_map(|result| result.err())
+                .filter(|_| is_editing)
+                .map(|err| (err.setting().to_owned(), err.message().to_owned()))
+                .collect();
+
+            store_profile(&mut settings);
+            store_room_set(&mut settings);
+            store_batch(&mut settings);
+            store_alias(&mut settings);
+        }
+
+        let layout = cached_layout(&mut layout_cache, settings, &output_list, &zone_list);
+        let layout = layout.serialize(serde_json::value::Serializer).unwrap();
+
+        send_complete!(resp_props, "Success", Some(json!({"settings": layout})));
+
+        if !is_dry_run && !layout["has_error"].as_bool().unwrap() {
+            send_continue_all!(resp_props, "subscribe_settings", "Changed", Some(json!({"settings": layout})));
+        }
+
+        resp_props
+    };
+    let (svc, settings) = Settings::new(&roon, Box::new(get_settings_cb), Box::new(save_settings_cb));
+
+    provided.insert(settings::SVCNAME.to_owned(), svc);
+
+    let (svc, status) = Status::new(&roon);
+
+    provided.insert(status::SVCNAME.to_owned(), svc);
+
+    let services = vec![
+        Services::Settings(settings),
+        Services::Status(status),
+        Services::Transport(Transport::new()),
+        Services::Browse(Browse::new())
+    ];
+    let (mut handles, mut core_rx) = roon.start_discovery(provided, Some(services)).await.unwrap();
+    let (request_tx, mut request_rx) = mpsc::unbounded_channel::<(Trigger, std::sync::mpsc::Sender<String>)>();
+
+    #[cfg(feature = "http")]
+    http::start_from_env(http::HttpState {
+        settings: saved_settings.clone(),
+        outputs: output_list.clone(),
+        zones: zone_list.clone(),
+        status: status_message.clone(),
+        status_changed: status_changed.clone(),
+        requests: request_tx
+    });
+    #[cfg(not(feature = "http"))]
+    drop(request_tx);
+
+    let core_handler = async move {
+        let mut status = StatusReporter {
+            status: None,
+            message: status_message,
+            changed: status_changed,
+            verbose: is_verbose()
+        };
+        let mut transport = None;
+        let mut browse: Option<Browse> = None;
+        let mut pending_play: Option<PendingPlay> = None;
+        let mut matched_zone: Option<MatchedZone> = None;
+        // A matched zone that disappeared, no preset is reported active when it doesn't return in time
+        let mut lost_zone: Option<(MatchedZone, tokio::time::Instant)> = None;
+        let mut volumes_applied_zone_id: Option<String> = None;
+        let mut settings_key = LEGACY_SETTINGS_KEY.to_owned();
+        let mut is_legacy_settings = false;
+        let search_start = Instant::now();
+        let mut is_core_found = false;
+        let mut search_deadline = tokio::time::Instant::now() + CORE_SEARCH_INTERVAL;
+        let mut last_volumes = serde_json::from_value::<HashMap<String, f32>>(RoonApi::load_config("last_volumes")).unwrap_or_default();
+        // Volume changes arrive in bursts while a knob is turned, they are saved once it settles
+        let mut last_volumes_deadline: Option<tokio::time::Instant> = None;
+        let mut pending_groups: Vec<PendingGroup> = Vec::new();
+        let mut group_latencies: HashMap<String, Vec<u128>> = HashMap::new();
+        let mut last_activations: HashMap<String, Instant> = HashMap::new();
+        let mut pending_volumes: HashMap<String, PendingVolume> = HashMap::new();
+        let mut pending_waits: Vec<PendingWait> = Vec::new();
+        let mut is_wait_over = false;
+        let mut is_trigger = false;
+        let mut wait_missing: Vec<String> = Vec::new();
+        let mut last_action: Option<((Option<usize>, usize), Instant)> = None;
+        let mut log_writes: Vec<tokio::task::JoinHandle<()>> = Vec::new();
+        // Set on exit while the ungroup of the active zone is awaited
+        let mut exit_zone_id: Option<String> = None;
+        let mut exit_deadline: Option<tokio::time::Instant> = None;
+        // Created once, a signal arriving between two iterations would otherwise be missed
+        let ctrl_c = tokio::signal::ctrl_c();
+
+        tokio::pin!(ctrl_c);
+
+        loop {
+            let group_deadline = pending_groups
+                .iter()
+                .map(|pending| pending.deadline)
+                .min()
+                .unwrap_or_else(tokio::time::Instant::now);
+            let volume_deadline = pending_volumes
+                .values()
+                .map(|pending| pending.deadline)
+                .min()
+                .unwrap_or_else(tokio::time::Instant::now);
+            let wait_deadline = pending_waits
+                .iter()
+                .map(|pending| pending.deadline)
+                .min()
+                .unwrap_or_else(tokio::time::Instant::now);
+            let grace_deadline = lost_zone
+                .as_ref()
+                .map_or_else(tokio::time::Instant::now, |(_, deadline)| *deadline);
+            let (event, reply) = tokio::select! {
+                event = core_rx.recv() => (event.map(|(core, msg)| (Some(core), msg.map(|(_, parsed)| parsed))), None),
+                Some((trigger, reply)) = request_rx.recv() => {
+                    let settings = trigger_settings(&trigger, &output_list.lock().unwrap(), &saved_settings.lock().unwrap());
+
+                    match settings {
+                        Some(settings) => {
+                            // Carried out like an action saved from the UI, without replacing the saved settings
+                            is_trigger = true;
+                            (Some((None, Some(Parsed::SettingsSaved(json!(settings))))), Some(reply))
+                        }
+                        None => {
+                            let _ = reply.send(format!("Unknown preset \"{}\"", trigger.preset_name));
+                            continue;
+                        }
+                    }
+                }
+                _ = tokio::time::sleep_until(group_deadline), if !pending_groups.is_empty() => {
+                    let now = tokio::time::Instant::now();
+                    let (expired, waiting): (Vec<PendingGroup>, Vec<PendingGroup>) = pending_groups
+                        .drain(..)
+                        .partition(|pending| pending.deadline <= now);
+
+                    pending_groups = waiting;
+
+                    let aliases = saved_settings.lock().unwrap().output_aliases.to_owned();
+
+                    for pending in expired {
+                        let missing = missing_outputs(&pending.output_ids, &zone_list.lock().unwrap());
+                        let status_msg = if status.verbose {
+                            activation_detail(&pending, &missing, &pending_volumes, &output_list.lock().unwrap(), &aliases)
+                        } else {
+                            format!("Failed to group \"{}\"", pending.preset_name)
+                        };
+
+                        println!("Failed to group \"{}\", outputs not joined: {:?}", pending.preset_name, missing);
+
+                        status.set_status(status_msg, true).await;
+                    }
+
+                    continue;
+                }
+                _ = tokio::time::sleep_until(wait_deadline), if !pending_waits.is_empty() => {
+                    let now = tokio::time::Instant::now();
+
+                    match pending_waits.iter().position(|pending| pending.deadline <= now).map(|index| pending_waits.remove(index)) {
+                        Some(pending) => {
+                            let output_list = output_list.lock().unwrap();
+
+                            wait_missing = pending.output_ids
+                                .iter()
+                                .filter(|output_id| !output_list.contains_key(*output_id))
+                                .cloned()
+                                .collect();
+
+                            if !wait_missing.is_empty() {
+                                println!("Activating \"{}\" without outputs that did not appear: {:?}", pending.preset_name, wait_missing);
+                            }
+
+                            let trigger = Trigger {
+                                preset_name: pending.preset_name.to_owned(),
+                                action: Action::Activate
+                            };
+
+                            // Resume with the settings as they are now, the preset may have been edited meanwhile
+                            match trigger_settings(&trigger, &output_list, &saved_settings.lock().unwrap()) {
+                                Some(settings) => {
+                                    // Resume the activation, it is not a repeat of the postponed one
+                                    is_wait_over = true;
+                                    is_trigger = true;
+                                    last_action = None;
+                                    (Some((None, Some(Parsed::SettingsSaved(json!(settings))))), None)
+                                }
+                                None => {
+                                    println!("Preset \"{}\" was removed while waiting for its outputs", pending.preset_name);
+                                    continue;
+                                }
+                            }
+                        }
+                        None => continue
+                    }
+                }
+                _ = tokio::time::sleep_until(grace_deadline), if lost_zone.is_some() => {
+                    if let Some((lost, _)) = lost_zone.take() {
+                        // Another match may have been found in the meantime
+                        if matched_zone.is_none() {
+                            println!("Zone \"{}\" did not return", lost.zone_name);
+
+                            status.set_status("No preset active".to_owned(), false).await;
+                        }
+                    }
+
+                    continue;
+                }
+                _ = tokio::time::sleep_until(search_deadline), if !is_core_found => {
+                    println!("No Roon core found, still searching...");
+
+                    search_deadline += CORE_SEARCH_INTERVAL;
+
+                    continue;
+                }
+                _ = tokio::time::sleep_until(volume_deadline), if !pending_volumes.is_empty() => {
+                    let now = tokio::time::Instant::now();
+                    let expired: Vec<String> = pending_volumes
+                        .iter()
+                        .filter(|(_, pending)| pending.deadline <= now)
+                        .map(|(output_id, _)| output_id.to_owned())
+                        .collect();
+
+                    for output_id in expired {
+                        if let Some(mut pending) = pending_volumes.remove(&output_id) {
+                            let display_name = output_list.lock().unwrap()
+                                .get(&output_id)
+                                .map_or_else(|| output_id.to_owned(), |output| output.display_name.to_owned());
+
+                            if pending.attempts >= VOLUME_RETRIES {
+                                println!("Output \"{}\" did not reach volume {}, giving up", display_name, pending.target);
+                            } else if let Some(transport) = transport.as_ref() {
+                                pending.attempts += 1;
+
+                                println!(
+                                    "Retrying volume {} for \"{}\" (attempt {} of {})",
+                                    pending.target,
+                                    display_name,
+                                    pending.attempts,
+                                    VOLUME_RETRIES
+                                );
+                                for (how, value) in volume_requests(pending.target, pending.step) {
+                                    transport.change_volume(&output_id, how, value).await;
+                                }
+
+                                // Back off exponentially to give slow outputs time to respond
+                                pending.deadline = now + VOLUME_RETRY_DELAY * 2u32.pow(pending.attempts);
+                                pending_volumes.insert(output_id, pending);
+                            }
+                        }
+                    }
+
+                    continue;
+                }
+                _ = tokio::time::sleep_until(last_volumes_deadline.unwrap_or_else(tokio::time::Instant::now)), if last_volumes_deadline.is_some() => {
+                    last_volumes_deadline = None;
+                    save_last_volumes(&last_volumes);
+
+                    continue;
+                }
+                _ = tokio::time::sleep_until(exit_deadline.unwrap_or_else(tokio::time::Instant::now)), if exit_deadline.is_some() => {
+                    println!("Ungroup on exit not confirmed in time");
+                    break;
+                }
+                _ = &mut ctrl_c => {
+                    let deactivate_on_exit = saved_settings.lock().unwrap().deactivate_on_exit;
+
+                    // Armed again for the second interrupt
+                    ctrl_c.set(tokio::signal::ctrl_c());
+
+                    // A second interrupt doesn't wait any longer
+                    if exit_deadline.is_some() {
+                        break;
+                    }
+
+                    if deactivate_on_exit {
+                        let zone = matched_zone
+                            .as_ref()
+                            .and_then(|matched| zone_list.lock().unwrap().get(&matched.zone_id).cloned());
+
+                        if let (Some(transport), Some(zone)) = (transport.as_ref(), zone) {
+                            let output_ids = zone.outputs
+                                .iter()
+                                .map(|output| output.output_id.as_str())
+                                .collect();
+
+                            println!("Deactivating zone \"{}\" on exit", zone.display_name);
+                            transport.ungroup_outputs(output_ids).await;
+
+                            // The request is only queued, wait for the zones to confirm it was sent
+                            exit_zone_id = Some(zone.zone_id.to_owned());
+                            exit_deadline = Some(tokio::time::Instant::now() + EXIT_TIMEOUT);
+                            continue;
+                        }
+                    }
+
+                    break;
+                }
+            };
+
+            if let Some((core, msg)) = event {
+                match core {
+                    Some(CoreEvent::Found(mut core)) => {
+                        println!("Core found: {}, version {}", core.display_name, core.display_version);
+
+                        if !is_core_found {
+                            is_core_found = true;
+
+                            println!("Connected after searching for {}s", search_start.elapsed().as_secs());
+                        }
+
+                        status.status = core.get_status().cloned();
+
+                        // Presets refer to output ids of a single core, keep them apart
+                        settings_key = format!("settings_{}", core.core_id);
+
+                        let settings = load_settings(&settings_key);
+
+                        // Legacy settings may have been saved while connected to another core
+                        is_legacy_settings = settings.is_none();
+
+                        let settings = settings
+                            .or_else(|| load_settings(LEGACY_SETTINGS_KEY))
+                            .unwrap_or_default();
+
+                        *saved_settings.lock().unwrap() = settings;
+                        layout_cache.lock().unwrap().take();
+                        matched_zone = None;
+
+                        let status_msg = {
+                            let settings = saved_settings.lock().unwrap();
+
+                            if settings.is_auto_match_enabled() {
+                                startup_status(&settings.presets)
+                            } else {
+                                AUTO_MATCH_DISABLED.to_owned()
+                            }
+                        };
+
+                        status.set_status(status_msg, false).await;
+
+                        transport = core.get_transport().cloned();
+                        browse = core.get_browse().cloned();
+
+                        if let Some(transport) = transport.as_ref() {
+                            transport.subscribe_zones().await;
+                            transport.subscribe_outputs().await;
+                        }
+                    }
+                    Some(CoreEvent::Lost(core)) => {
+                        println!("Core lost: {}, version {}", core.display_name, core.display_version);
+                    }
+                    _ => ()
+                }
+
+                if let Some(parsed) = msg {
+                    match parsed {
+                        Parsed::Zones(zones) => {
+                            let (presets, auto_match_enabled, template, safety_cap) = {
+                                let settings = saved_settings.lock().unwrap();
+
+                                (
+                                    resolve_room_sets(&settings.presets, &settings.room_sets),
+                                    settings.is_auto_match_enabled(),
+                                    settings.status_matched.to_owned(),
+                                    settings.safety_cap()
+                                )
+                            };
+
+                            let is_split = matched_zone.as_ref().map_or(false, |matched| is_split(matched, &presets, &zones));
+
+                            if is_split {
+                                if let Some(matched) = matched_zone.take() {
+                                    println!("Zone \"{}\" no longer matches the \"{}\" preset", matched.zone_name, matched.preset_name);
+                                }
+
+                                volumes_applied_zone_id = None;
+                            }
+
+                            if let Some(matched) = matched_zone.as_mut() {
+                                let zone = zones.iter().find(|zone| zone.zone_id == matched.zone_id);
+                                let preset = presets.iter().find(|preset| preset.name == matched.preset_name);
+
+                                if let (Some(zone), Some(preset)) = (zone, preset) {
+                                    let status_msg = matched_status(preset, zone, &template);
+
+                                    if zone.display_name != matched.zone_name {
+                                        println!("Zone \"{}\" renamed to \"{}\"", matched.zone_name, zone.display_name);
+
+                                        matched.zone_name = zone.display_name.to_owned();
+                                    }
+
+                                    let master_name = volume_master_name(zone).map(|name| name.to_owned());
+
+                                    if master_name != matched.master_name {
+                                        println!("Volume master of zone \"{}\" is now {}", zone.display_name, master_name.as_deref().unwrap_or("unknown"));
+
+                                        matched.master_name = master_name;
+                                    }
+
+                                    // Only update on actual changes, seek updates also arrive as Zones events
+                                    if status_msg != matched.status_msg {
+                                        matched.status_msg = status_msg.to_owned();
+
+                                        status.set_status(status_msg, false).await;
+                                    }
+                                }
+                            } else if let Some((matching_preset, zone)) = auto_match_enabled.then(|| match_preset(&presets, &zones)).flatten() {
+                                let status_msg = matched_status(matching_preset, zone, &template);
+
+                                // Presets grouped by this extension already got their volumes
+                                let is_activated = pending_groups
+                                    .iter()
+                                    .any(|pending| pending.preset_name == matching_preset.name);
+
+                                if let Some((lost, _)) = lost_zone.take() {
+                                    if lost.preset_name == matching_preset.name {
+                                        println!("Zone \"{}\" returned within the grace period", zone.display_name);
+                                    }
+                                }
+
+                                matched_zone = Some(MatchedZone {
+                                    zone_id: zone.zone_id.to_owned(),
+                                    zone_name: zone.display_name.to_owned(),
+                                    preset_name: matching_preset.name.to_owned(),
+                                    master_name: volume_master_name(zone).map(|name| name.to_owned()),
+                                    status_msg: status_msg.to_owned()
+                                });
+
+                                // Rematching the same zone doesn't start a new session, volumes are applied once until it dissolves
+                                let is_applied = volumes_applied_zone_id.as_ref() == Some(&zone.zone_id);
+
+                                if is_activated {
+                                    volumes_applied_zone_id = Some(zone.zone_id.to_owned());
+                                }
+
+                                if let (true, false, false, Some(transport)) = (
+                                    matching_preset.apply_volume_on_match && matching_preset.is_armed(),
+                                    is_activated,
+                                    is_applied,
+                                    transport.as_ref()
+                                ) {
+                                    let output_ids: Vec<&str> = matching_preset.output_ids.iter().map(|output_id| output_id.as_str()).collect();
+                                    let commands = plan_volumes(matching_preset, &output_ids, &output_list.lock().unwrap(), &last_volumes, safety_cap);
+
+                                    println!("Applying volumes of manually grouped preset \"{}\"", matching_preset.name);
+
+                                    volumes_applied_zone_id = Some(zone.zone_id.to_owned());
+
+                                    execute_commands(transport, &commands).await;
+                                    track_volumes(&mut pending_volumes, &commands);
+                                }
+
+                                status.set_status(status_msg, false).await;
+                            }
+
+                            if is_split && matched_zone.is_none() {
+                                status.set_status("No preset active".to_owned(), false).await;
+                            }
+
+                            let detail = {
+                                let mut settings = saved_settings.lock().unwrap();
+
+                                settings.extracted_preset = extract_preset(&zones);
+
+                                let mut zone_list = zone_list.lock().unwrap();
+                                let is_regrouped = zones.iter().any(|zone| {
+                                    zone_list
+                                        .get(&zone.zone_id)
+                                        .map_or(true, |known| zone_members(known) != zone_members(zone))
+                                });
+
+                                if is_regrouped {
+                                    layout_cache.lock().unwrap().take();
+                                }
+
+                                for zone in zones {
+                                    zone_list.insert(zone.zone_id.to_owned(), zone);
+                                }
+
+                                if is_regrouped && status.verbose {
+                                    for near_miss in find_near_misses(&presets, zone_list.values(), &settings.output_aliases) {
+                                        println!("{}", near_miss);
+                                    }
+                                }
+
+                                let (grouped, waiting): (Vec<PendingGroup>, Vec<PendingGroup>) = pending_groups
+                                    .drain(..)
+                                    .partition(|pending| missing_outputs(&pending.output_ids, &zone_list).is_empty());
+
+                                pending_groups = waiting;
+
+                                let details: Vec<String> = grouped
+                                    .iter()
+                                    .filter_map(|pending| {
+                                        let latency_ms = pending.started.elapsed().as_millis();
+                                        let samples = group_latencies.entry(pending.preset_name.to_owned()).or_default();
+
+                                        if samples.len() == GROUP_LATENCY_SAMPLES {
+                                            samples.remove(0);
+                                        }
+
+                                        samples.push(latency_ms);
+                                        println!(
+                                            "Grouped \"{}\" in {} ms, average {} ms over the last {} activations",
+                                            pending.preset_name,
+                                            latency_ms,
+                                            samples.iter().sum::<u128>() / samples.len() as u128,
+                                            samples.len()
+                                        );
+
+                                        status.verbose.then(|| {
+                                            activation_detail(pending, &[], &pending_volumes, &output_list.lock().unwrap(), &settings.output_aliases)
+                                        })
+                                    })
+                                    .collect();
+
+                                (!details.is_empty()).then(|| details.join("\n"))
+                            };
+
+                            if let Some(detail) = detail {
+                                status.set_status(detail, false).await;
+                            }
+                        }
+                        Parsed::ZonesRemoved(removed_zone_ids) => {
+                            layout_cache.lock().unwrap().take();
+
+                            for zone_id in &removed_zone_ids {
+                                zone_list.lock().unwrap().remove(zone_id);
+                            }
+
+                            if volumes_applied_zone_id.as_ref().map_or(false, |zone_id| removed_zone_ids.contains(zone_id)) {
+                                volumes_applied_zone_id = None;
+                            }
+
+                            if let Some(matched) = &matched_zone {
+                                if removed_zone_ids.contains(&matched.zone_id) {
+                                    let grace_ms = saved_settings.lock().unwrap().status_grace_ms.trim().parse::<u64>().unwrap_or_default();
+
+                                    if grace_ms > 0 {
+                                        let deadline = tokio::time::Instant::now() + Duration::from_millis(grace_ms);
+
+                                        lost_zone = matched_zone.take().map(|matched| (matched, deadline));
+                                    } else {
+                                        matched_zone = None;
+
+                                        status.set_status("No preset active".to_owned(), false).await;
+                                    }
+                                }
+                            }
+                        }
+                        Parsed::Outputs(outputs) => {
+                            let is_first = output_list.lock().unwrap().is_empty();
+                            let mut is_changed = false;
+                            let mut changed_ids = Vec::new();
+
+                            layout_cache.lock().unwrap().take();
+
+                            for output in outputs {
+                                let output_id = output.output_id.to_owned();
+                                let mut output_list = output_list.lock().unwrap();
+
+                                if let Some(volume) = output.volume.as_ref() {
+                                    let volume_level = live_volume_level(volume);
+                                    // Levels the extension set itself are echoes, they are not mirrored
+                                    let is_echo = pending_volumes.contains_key(&output_id);
+
+                                    if pending_volumes.get(&output_id).map_or(false, |pending| volume_reached(volume, pending.target)) {
+                                        pending_volumes.remove(&output_id);
+                                    }
+
+                                    if last_volumes.insert(output_id.to_owned(), volume_level) != Some(volume_level) {
+                                        is_changed = true;
+
+                                        if !is_echo {
+                                            changed_ids.push(output_id.to_owned());
+                                        }
+                                    }
+                                }
+
+                                output_list.insert(output_id, output);
+                            }
+
+                            if is_changed && last_volumes_deadline.is_none() {
+                                last_volumes_deadline = Some(tokio::time::Instant::now() + LAST_VOLUMES_SAVE_DELAY);
+                            }
+
+                            let mut settings = saved_settings.lock().unwrap();
+
+                            if settings.match_by_name_fallback {
+                                let output_list = output_list.lock().unwrap();
+                                let mut is_resolved = false;
+
+                                for index in 0..settings.presets.len() {
+                                    let resolved = resolve_by_name(&mut settings.presets[index], &output_list);
+
+                                    for (old_id, new_id) in &resolved {
+                                        println!(
+                                            "Preset \"{}\": resolved output \"{}\" by name",
+                                            settings.presets[index].name,
+                                            output_list.get(new_id).map_or("", |output| output.display_name.as_str())
+                                        );
+
+                                        if settings.selected == Some(index) {
+                                            settings.replace_output_id(old_id, new_id);
+                                        }
+                                    }
+
+                                    is_resolved |= !resolved.is_empty();
+                                }
+
+                                if is_resolved {
+                                    let mut nv_settings = json!(*settings);
+
+                                    nv_settings["extracted_preset"] = Value::Null;
+                                    RoonApi::save_config(&settings_key, nv_settings).unwrap();
+                                    // Saved under the key of this core from now on
+                                    is_legacy_settings = false;
+                                }
+                            }
+
+                            drop(settings);
+
+                            if let (Some(matched), Some(transport), false) = (&matched_zone, transport.as_ref(), changed_ids.is_empty()) {
+                                let commands = {
+                                    let output_list = output_list.lock().unwrap();
+                                    let settings = saved_settings.lock().unwrap();
+
+                                    settings.presets
+                                        .iter()
+                                        .find(|preset| preset.name == matched.preset_name)
+                                        .map(|preset| plan_mirror(preset, &changed_ids, &output_list, settings.safety_cap()))
+                                        .unwrap_or_default()
+                                };
+
+                                if !commands.is_empty() {
+                                    execute_commands(transport, &commands).await;
+                                    track_volumes(&mut pending_volumes, &commands);
+                                }
+                            }
+
+                            for pending in pending_waits.iter_mut() {
+                                let output_list = output_list.lock().unwrap();
+
+                                if pending.output_ids.iter().all(|output_id| output_list.contains_key(output_id)) {
+                                    // All members are here, no need to wait any longer
+                                    pending.deadline = tokio::time::Instant::now();
+                                }
+                            }
+
+                            if is_first {
+                                let presets = saved_settings.lock().unwrap().presets.to_owned();
+
+                                for summary in describe_presets(&presets, &output_list.lock().unwrap()) {
+                                    println!("Preset \"{}\": {} (volume: {})", summary.name, summary.members.join(" + "), summary.volume_mode);
+                                }
+
+                                // Flagged only, the outputs may just be offline
+                                for preset in &presets {
+                                    let output_list = output_list.lock().unwrap();
+                                    let unknown: Vec<&str> = preset.output_ids
+                                        .iter()
+                                        .filter(|output_id| !output_list.contains_key(*output_id))
+                                        .map(|output_id| preset.output_names.get(output_id).map_or(output_id.as_str(), |name| name.as_str()))
+                                        .collect();
+
+                                    if !unknown.is_empty() {
+                                        println!("Preset \"{}\" references outputs not present on this core: {}", preset.name, unknown.join(", "));
+                                    }
+                                }
+
+                                let adjusted = {
+                                    let output_list = output_list.lock().unwrap();
+                                    let mut settings = saved_settings.lock().unwrap();
+                                    let adjusted = fit_preset_volumes(&mut settings.presets, &output_list);
+
+                                    if adjusted > 0 {
+                                        let mut nv_settings = json!(*settings);
+
+                                        nv_settings["extracted_preset"] = Value::Null;
+                                        RoonApi::save_config(&settings_key, nv_settings).unwrap();
+                                        is_legacy_settings = false;
+                                    }
+
+                                    adjusted
+                                };
+
+                                if adjusted > 0 {
+                                    status.set_status(format!("Adjusted {} stored volumes to the range of their outputs", adjusted), false).await;
+                                }
+                            }
+                        }
+                        Parsed::OutputsRemoved(output_ids) => {
+                            layout_cache.lock().unwrap().take();
+
+                            let mut output_list = output_list.lock().unwrap();
+
+                            for output_id in output_ids {
+                                output_list.remove(&output_id);
+                            }
+                        }
+                        Parsed::BrowseResult(result, _) => {
+                            if let (Some(browse), Some(pending)) = (browse.as_ref(), pending_play.as_ref()) {
+                                if result.action == "list" {
+                                    let opts = LoadOpts {
+                                        hierarchy: RADIO_HIERARCHY.to_owned(),
+                                        count: Some(RADIO_LOAD_COUNT),
+                                        ..Default::default()
+                                    };
+
+                                    browse.load(&opts).await;
+                                } else {
+                                    let (status_msg, is_error) = match result.message {
+                                        Some(message) if result.is_error => (message, true),
+                                        _ => (format!("Started {} on {}", pending.station, pending.preset_name), false)
+                                    };
+
+                                    pending_play = None;
+                                    status.set_status(status_msg, is_error).await;
+                                }
+                            }
+                        }
+                        Parsed::LoadResult(result, _) => {
+                            if let (Some(browse), Some(pending)) = (browse.as_ref(), pending_play.as_mut()) {
+                                let item = match pending.step {
+                                    PlayStep::Station => result.items
+                                        .iter()
+                                        .find(|item| item.title.eq_ignore_ascii_case(&pending.station)),
+                                    // Stations can come with a list of actions, the first one plays
+                                    PlayStep::Action => result.items
+                                        .iter()
+                                        .find(|item| item.hint.as_deref() == Some("action"))
+                                };
+
+                                match item.and_then(|item| item.item_key.to_owned()) {
+                                    Some(item_key) => {
+                                        let opts = BrowseOpts {
+                                            hierarchy: RADIO_HIERARCHY.to_owned(),
+                                            item_key: Some(item_key),
+                                            zone_or_output_id: Some(pending.output_id.to_owned()),
+                                            ..Default::default()
+                                        };
+
+                                        pending.step = PlayStep::Action;
+                                        browse.browse(&opts).await;
+                                    }
+                                    None => {
+                                        let status_msg = format!("Radio station \"{}\" not found", pending.station);
+
+                                        pending_play = None;
+                                        status.set_status(status_msg, true).await;
+                                    }
+                                }
+                            }
+                        }
+                        Parsed::SettingsSaved(settings) => {
+                            // Set when the settings were built for an HTTP request or a resumed wait instead of saved from the UI
+                            let is_triggered = std::mem::take(&mut is_trigger);
+                            let mut nv_settings = settings.to_owned();
+
+                            nv_settings["extracted_preset"] = serde_json::Value::Null;
+
+                            if let Ok(mut settings) = serde_json::from_value::<GroupingSettings>(settings) {
+                                // Set when this is a postponed activation that waited for its outputs
+                                let is_resumed = std::mem::take(&mut is_wait_over);
+                                let wait_missing = std::mem::take(&mut wait_missing);
+                                let mut status_msg = "Settings saved".to_owned();
+                                let mut is_error = false;
+                                let action_key = (settings.selected, settings.action.to_owned() as usize);
+                                let is_repeated = match &last_action {
+                                    Some((key, instant)) => *key == action_key && instant.elapsed() < ACTION_DEBOUNCE,
+                                    None => false
+                                };
+
+                                if settings.action.is_execution() {
+                                    if !is_repeated {
+                                        last_action = Some((action_key, Instant::now()));
+                                    }
+                                }
+
+                                if is_repeated {
+                                    println!("Ignoring repeated action for preset \"{}\"", settings.name);
+                                } else if let Action::Edit = settings.action {
+                                    // A plain save refreshes the zones for the layout, nothing gets (re)activated
+                                    if let Some(transport) = transport.as_ref() {
+                                        transport.get_zones().await;
+                                    }
+                                } else if let Action::UngroupAll = settings.action {
+                                    // Dissolves the groups of the household, whichever preset happens to be selected
+                                    if let Some(transport) = transport.as_ref() {
+                                        let zones: Vec<Zone> = zone_list.lock().unwrap().values().cloned().collect();
+                                        let commands = plan_ungroup_all(&zones);
+
+                                        execute_commands(transport, &commands).await;
+                                        status_msg = format!("Dissolved {} groups", commands.len());
+                                    }
+                                } else if settings.action.is_execution() && settings.selected.is_some() && settings.primary_output_id.is_some() {
+                                    if let Some(transport) = transport.as_ref() {
+                                        let presets = resolve_room_sets(&settings.presets, &settings.room_sets);
+                                        let mut member_ids = settings.output_ids.to_owned();
+
+                                        if let Some(preset) = settings.selected.and_then(|selected| presets.get(selected)) {
+                                            for output_id in &preset.output_ids {
+                                                if !member_ids.contains(output_id) {
+                                                    member_ids.push(output_id.to_owned());
+                                                }
+                                            }
+                                        }
+
+                                        let output_ids: Vec<&str> = member_ids
+                                            .iter()
+                                            .filter_map(|output_id| {
+                                                if output_list.lock().unwrap().contains_key(output_id) {
+                                                    Some(output_id.as_str())
+                                                } else {
+                                                    None
+                                                }
+                                            })
+                                            .collect();
+
+                                        match settings.action {
+                                            Action::Activate | Action::ActivateBatch => {
+                                                // Each preset is planned with the zones the previous ones of a batch left
+                                                let mut zones: Vec<Zone> = zone_list.lock().unwrap().values().cloned().collect();
+                                                let mut state = ActivationState {
+                                                    transport,
+                                                    browse: browse.as_ref(),
+                                                    outputs: &output_list,
+                                                    matched_zone: matched_zone.as_ref(),
+                                                    last_volumes: &last_volumes,
+                                                    is_legacy_settings,
+                                                    pending_groups: &mut pending_groups,
+                                                    pending_volumes: &mut pending_volumes,
+                                                    pending_waits: &mut pending_waits,
+                                                    pending_play: &mut pending_play,
+                                                    last_activations: &mut last_activations,
+                                                    log_writes: &mut log_writes
+                                                };
+
+                                                if let Action::Activate = settings.action {
+                                                    let activation = if is_resumed { Activation::Resumed(&wait_missing) } else { Activation::Single };
+                                                    let selected = settings.selected.unwrap();
+                                                    let (activated_msg, is_failed) = activate_preset(
+                                                        &mut state,
+                                                        &mut settings,
+                                                        &mut nv_settings,
+                                                        &presets,
+                                                        selected,
+                                                        &mut zones,
+                                                        activation
+                                                    ).await;
+
+                                                    status_msg = activated_msg;
+                                                    is_error = is_failed;
+                                                } else {
+                                                    let mut results = Vec::new();
+
+                                                    // Failures are reported per preset, the rest of the batch still goes ahead
+                                                    for index in batch_order(&presets, &settings.batch) {
+                                                        let (activated_msg, is_failed) = activate_preset(
+                                                            &mut state,
+                                                            &mut settings,
+                                                            &mut nv_settings,
+                                                            &presets,
+                                                            index,
+                                                            &mut zones,
+                                                            Activation::Batch
+                                                        ).await;
+
+                                                        results.push(activated_msg);
+                                                        is_error |= is_failed;
+                                                    }
+
+                                                    status_msg = if results.is_empty() {
+                                                        "No presets selected to activate".to_owned()
+                                                    } else {
+                                                        results.join("; ")
+                                                    };
+                                                }
+                                            }
+                                            Action::Deactivate => {
+                                                let selected = settings.selected.unwrap();
+                                                let zones: Vec<Zone> = zone_list.lock().unwrap().values().cloned().collect();
+                                                let commands = plan_action(&settings.action, &PlanInput {
+                                                    presets: &presets,
+                                                    selected,
+                                                    extracted_preset: None,
+                                                    output_ids: &output_ids,
+                                                    zones: &zones,
+                                                    outputs: &output_list.lock().unwrap(),
+                                                    last_volumes: &last_volumes,
+                                                    safety_cap: settings.safety_cap()
+                                                });
+                                                let ungroup_ids = ungrouped_output_ids(&commands);
+
+                                                if let (Some(preset), Some(live)) = (settings.presets.get_mut(selected), presets.get(selected)) {
+                                                    if let VolumeType::LastUsed = preset.volume_type {
+                                                        let volumes = &mut nv_settings["presets"].get_mut(selected).unwrap()["volumes"];
+
+                                                        for (output_id, volume_level) in captured_volumes(live, &zones, &output_list.lock().unwrap()) {
+                                                            volumes[&output_id] = volume_level.into();
+                                                            preset.volumes.insert(output_id, volume_level);
+                                                        }
+                                                    }
+                                                }
+
+                                                let ungroup_count = ungroup_ids.len();
+
+                                                log_writes.retain(|write| !write.is_finished());
+                                                log_writes.extend(log_event(&settings.name, "deactivate", &ungroup_ids));
+                                                execute_commands(transport, &commands).await;
+
+                                                // The volumes of the group are final now, they don't wait for the delay
+                                                if last_volumes_deadline.take().is_some() {
+                                                    save_last_volumes(&last_volumes);
+                                                }
+
+                                                status_msg = render_status(&settings.status_deactivated, STATUS_DEACTIVATED, &[
+                                                    ("preset", settings.name.to_owned()),
+                                                    ("count", ungroup_count.to_string())
+                                                ]);
+                                            }
+                                            Action::ApplyVolumes => {
+                                                let selected = settings.selected.unwrap();
+                                                let live_zone = matched_zone
+                                                    .as_ref()
+                                                    .filter(|matched| matched.preset_name == settings.name)
+                                                    .and_then(|matched| zone_list.lock().unwrap().get(&matched.zone_id).cloned());
+
+                                                match (settings.presets.get(selected), live_zone) {
+                                                    (Some(preset), Some(zone)) => {
+                                                        // Tuning aid, the live zone is left grouped as is
+                                                        let live_ids: Vec<&str> = zone.outputs.iter().map(|output| output.output_id.as_str()).collect();
+                                                        let output_list = output_list.lock().unwrap().to_owned();
+                                                        let commands = plan_volumes(preset, &live_ids, &output_list, &last_volumes, settings.safety_cap());
+
+                                                        execute_commands(transport, &commands).await;
+                                                        track_volumes(&mut pending_volumes, &commands);
+                                                        status_msg = format!("Applied volumes to {}", settings.name);
+                                                        note_safety_cap(&mut status_msg, &settings.safety_cap().engaged(preset, &live_ids, &output_list, &last_volumes, &settings.output_aliases));
+                                                    }
+                                                    _ => {
+                                                        status_msg = format!("Preset \"{}\" is not active", settings.name);
+                                                        is_error = true;
+                                                    }
+                                                }
+                                            }
+                                            Action::ToggleMute => {
+                                                let live_zone = matched_zone
+                                                    .as_ref()
+                                                    .filter(|matched| matched.preset_name == settings.name)
+                                                    .and_then(|matched| zone_list.lock().unwrap().get(&matched.zone_id).cloned());
+
+                                                match live_zone {
+                                                    Some(zone) => {
+                                                        let commands = plan_toggle_mute(&zone);
+                                                        let is_muted = commands.iter().any(|command| matches!(command, TransportCommand::Mute(_, true)));
+
+                                                        execute_commands(transport, &commands).await;
+                                                        status_msg = if is_muted {
+                                                            format!("Preset \"{}\" muted", settings.name)
+                                                        } else {
+                                                            format!("Preset \"{}\" unmuted", settings.name)
+                                                        };
+                                                    }
+                                                    None => {
+                                                        status_msg = format!("Preset \"{}\" is not active", settings.name);
+                                                        is_error = true;
+                                                    }
+                                                }
+                                            }
+                                            _ => ()
+                                        }
+                                    }
+                                }
+
+                                if let Action::Delete = settings.action {
+                                    matched_zone = None;
+                                    status_msg = format!("Preset \"{}\" deleted", settings.name);
+                                }
+
+                                if let Action::FindDuplicates = settings.action {
+                                    status_msg = describe_duplicates(&settings.presets, &find_duplicates(&settings.presets));
+                                }
+
+                                if let Action::MergeDuplicates = settings.action {
+                                    // The duplicates were already removed when the settings were saved
+                                    let merged = saved_settings.lock().unwrap().presets.len().saturating_sub(settings.presets.len());
+
+                                    matched_zone = None;
+                                    status_msg = format!("Merged {} duplicate presets", merged);
+                                }
+
+                                if let Action::CompactPresets = settings.action {
+                                    // The empty entries were already removed when the settings were saved
+                                    let removed = saved_settings.lock().unwrap().presets.len().saturating_sub(settings.presets.len());
+
+                                    status_msg = match removed {
+                                        0 => "No empty presets to remove".to_owned(),
+                                        1 => "Removed 1 empty preset".to_owned(),
+                                        count => format!("Removed {} empty presets", count)
+                                    };
+                                }
+
+                                if let Action::DumpDiagnostics = settings.action {
+                                    let last_status = status.message.lock().unwrap().to_owned();
+
+                                    dump_diagnostics(
+                                        &settings.presets,
+                                        &output_list.lock().unwrap(),
+                                        &zone_list.lock().unwrap(),
+                                        matched_zone.as_ref(),
+                                        &last_status
+                                    );
+                                    status_msg = "Diagnostics written to the log".to_owned();
+                                }
+
+                                if let Action::Arm | Action::Disarm = settings.action {
+                                    let state = if matches!(settings.action, Action::Arm) { "armed" } else { "disarmed" };
+
+                                    status_msg = format!("Preset \"{}\" {}", settings.name, state);
+                                }
+
+                                if let Action::Favorite | Action::Unfavorite = settings.action {
+                                    let change = if matches!(settings.action, Action::Favorite) { "added to" } else { "removed from" };
+
+                                    status_msg = format!("Preset \"{}\" {} favorites", settings.name, change);
+                                }
+
+                                if let Action::ResetVolumes = settings.action {
+                                    status_msg = format!("Cleared volume settings for \"{}\"", settings.name);
+                                }
+
+                                if let Action::ConfirmVolumeTypeAll = settings.action {
+                                    // The presets were already updated when the settings were saved
+                                    let updated = count_volume_type_changes(&saved_settings.lock().unwrap().presets, &settings.volume_type);
+
+                                    status_msg = format!("Updated the volume levels of {} presets", updated);
+                                }
+
+                                if let Action::ReplaceOutput = settings.action {
+                                    let preset = settings.selected.and_then(|selected| settings.presets.get(selected));
+
+                                    if let (Some(preset), Some(from), Some(to)) = (preset, &settings.replace_from, &settings.replace_to) {
+                                        if preset.output_ids.contains(to) && !preset.output_ids.contains(from) {
+                                            let output_list = output_list.lock().unwrap();
+                                            // The previous settings still know the name of a replaced offline output
+                                            let saved_settings = saved_settings.lock().unwrap();
+                                            let output_name = |output_id: &String| output_list
+                                                .get(output_id)
+                                                .map_or(saved_output_name(&saved_settings, output_id).to_owned(), |output| settings.output_name(output).to_owned());
+
+                                            matched_zone = None;
+                                            status_msg = format!(
+                                                "Replaced \"{}\" with \"{}\" in \"{}\"",
+                                                output_name(from),
+                                                output_name(to),
+                                                settings.name
+                                            );
+                                        }
+                                    }
+                                }
+
+                                if let Action::CopyVolume = settings.action {
+                                    let clamped = &settings.copy_clamped;
+
+                                    status_msg = if clamped.is_empty() {
+                                        format!("Copied volume to all outputs of \"{}\"", settings.name)
+                                    } else {
+                                        format!(
+                                            "Copied volume to all outputs of \"{}\", clamped: {}",
+                                            settings.name,
+                                            clamped.join(", ")
+                                        )
+                                    };
+                                }
+
+                                if !settings.is_auto_match_enabled() && matched_zone.take().is_some() && status_msg == "Settings saved" {
+                                    status_msg = AUTO_MATCH_DISABLED.to_owned();
+                                }
+
+                                if let Some(reply) = reply {
+                                    let _ = reply.send(status_msg.to_owned());
+                                }
+
+                                if !is_repeated {
+                                    status.set_status(status_msg, is_error).await;
+                                }
+
+                                let auto_match_enabled = settings.is_auto_match_enabled();
+                                let template = settings.status_matched.to_owned();
+                                let presets = resolve_room_sets(&settings.presets, &settings.room_sets);
+                                let is_membership_changed = if is_triggered {
+                                    let mut saved_settings = saved_settings.lock().unwrap();
+
+                                    // Only the usage of the preset is kept, the settings being edited stay as they are
+                                    keep_usage(&mut saved_settings, &settings);
+                                    nv_settings = json!(*saved_settings);
+                                    nv_settings["extracted_preset"] = Value::Null;
+                                    false
+                                } else {
+                                    let mut saved_settings = saved_settings.lock().unwrap();
+                                    let saved_presets = resolve_room_sets(&saved_settings.presets, &saved_settings.room_sets);
+
+                                    if *saved_settings.name != settings.name {
+                                        // A name change requires new matching
+                                        matched_zone = None;
+                                    }
+
+                                    *saved_settings = settings;
+                                    membership(&saved_presets) != membership(&presets)
+                                };
+
+                                // Don't wait for the next Zones event to reflect an edit in the status
+                                if is_membership_changed && auto_match_enabled {
+                                    let zones: Vec<Zone> = zone_list.lock().unwrap().values().cloned().collect();
+                                    let matched = match_preset(&presets, &zones).map(|(preset, zone)| MatchedZone {
+                                        zone_id: zone.zone_id.to_owned(),
+                                        zone_name: zone.display_name.to_owned(),
+                                        preset_name: preset.name.to_owned(),
+                                        master_name: volume_master_name(zone).map(|name| name.to_owned()),
+                                        status_msg: matched_status(preset, zone, &template)
+                                    });
+                                    let is_same = |a: &Option<MatchedZone>, b: &Option<MatchedZone>| match (a, b) {
+                                        (Some(a), Some(b)) => a.zone_id == b.zone_id && a.preset_name == b.preset_name,
+                                        (None, None) => true,
+                                        _ => false
+                                    };
+
+                                    if !is_same(&matched_zone, &matched) {
+                                        let status_msg = matched
+                                            .as_ref()
+                                            .map_or("No preset active".to_owned(), |matched| matched.status_msg.to_owned());
+
+                                        matched_zone = matched;
+                                        status.set_status(status_msg, false).await;
+                                    }
+                                }
+                            }
+
+                            RoonApi::save_config(&settings_key, nv_settings).unwrap();
+                            is_legacy_settings = false;
+                        }
+                        _ => ()
+                    }
+                }
+            }
+
+            if let Some(zone_id) = exit_zone_id.as_ref() {
+                let is_ungrouped = zone_list.lock().unwrap().get(zone_id).map_or(true, |zone| zone.outputs.len() < 2);
+
+                if is_ungrouped {
+                    break;
+                }
+            }
+        }
+
+        if last_volumes_deadline.is_some() {
+            save_last_volumes(&last_volumes);
+        }
+
+        // Settings are saved synchronously on SettingsSaved, only the event log may still be writing
+        for write in log_writes {
+            let _ = write.await;
+        }
+
+        println!("Clean shutdown");
+        std::process::exit(0);
+    };
+
+    handles.push(tokio::spawn(core_handler));
+
+    for handle in handles {
+        handle.await.unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OUTPUT_IDS: [&str; 4] = ["living", "kitchen", "study", "garden"];
+
+    fn volume_json(scale: &str, min: f32, max: f32, step: f32, level: f32) -> Value {
+        json!({
+            "type": scale,
+            "min": min,
+            "max": max,
+            "value": level,
+            "step": step,
+            "is_muted": false,
+            "hard_limit_min": min,
+            "hard_limit_max": max,
+            "soft_limit": max
+        })
+    }
+
+    // Outputs and zones come from the core, build them from the JSON it sends
+    fn member_json(output_id: &str, volume: Value) -> Value {
+        json!({
+            "output_id": output_id,
+            "zone_id": format!("zone_{}", output_id),
+            "display_name": output_id,
+            "can_group_with_output_ids": OUTPUT_IDS,
+            "volume": volume,
+            "source_controls": []
+        })
+    }
+
+    fn output_json(output_id: &str, level: f32) -> Value {
+        member_json(output_id, volume_json("number", 0.0, 100.0, 1.0, level))
+    }
+
+    fn output(output_id: &str, level: f32) -> Output {
+        serde_json::from_value(output_json(output_id, level)).unwrap()
+    }
+
+    // An output with a dB scale from -80 to 0
+    fn db_output(output_id: &str, step: f32, level: f32) -> Output {
+        serde_json::from_value(member_json(output_id, volume_json("db", -80.0, 0.0, step, level))).unwrap()
+    }
+
+    fn outputs(levels: &[(&str, f32)]) -> HashMap<String, Output> {
+        levels
+            .iter()
+            .map(|(output_id, level)| (output_id.to_string(), output(output_id, *level)))
+            .collect()
+    }
+
+    fn zone(zone_id: &str, output_ids: &[&str]) -> Zone {
+        let members: Vec<Value> = output_ids.iter().map(|output_id| output_json(output_id, 20.0)).collect();
+
+        serde_json::from_value(json!({
+            "zone_id": zone_id,
+            "display_name": zone_id,
+            "outputs": members,
+            "state": "stopped",
+            "is_next_allowed": false,
+            "is_previous_allowed": false,
+            "is_pause_allowed": false,
+            "is_play_allowed": true,
+            "is_seek_allowed": false,
+            "queue_items_remaining": 0,
+            "queue_time_remaining": 0,
+            "settings": {"loop": "disabled", "shuffle": false, "auto_radio": false}
+        })).unwrap()
+    }
+
+    fn preset(name: &str, output_ids: &[&str]) -> Preset {
+        Preset {
+            name: name.to_owned(),
+            output_ids: output_ids.iter().map(|output_id| output_id.to_string()).collect(),
+            ..Default::default()
+        }
+    }
+
+    fn with_volumes(mut preset: Preset, levels: &[(&str, f32)]) -> Preset {
+        preset.volume_type = VolumeType::Preset;
+        preset.volumes = levels.iter().map(|(output_id, level)| (output_id.to_string(), *level)).collect();
+        preset
+    }
+
+    fn activate(presets: &[Preset], output_ids: &[&str], zones: &[Zone], outputs: &HashMap<String, Output>, safety_cap: SafetyCap) -> Vec<TransportCommand> {
+        plan_activate(&PlanInput {
+            presets,
+            selected: 0,
+            extracted_preset: None,
+            output_ids,
+            zones,
+            outputs,
+            last_volumes: &HashMap::new(),
+            safety_cap
+        })
+    }
+
+    fn group(output_ids: &[&str]) -> TransportCommand {
+        TransportCommand::Group(output_ids.iter().map(|output_id| output_id.to_string()).collect())
+    }
+
+    fn change_volume(output_id: &str, level: f32) -> TransportCommand {
+        TransportCommand::ChangeVolume(output_id.to_owned(), level, 1.0)
+    }
+
+    #[test]
+    fn match_ignores_the_order_of_outputs() {
+        let presets = [preset("Downstairs", &["living", "kitchen"])];
+        let zones = [zone("zone_kitchen", &["kitchen", "living"])];
+        let (matched, zone) = match_preset(&presets, &zones).unwrap();
+
+        assert_eq!(matched.name, "Downstairs");
+        assert_eq!(zone.zone_id, "zone_kitchen");
+    }
+
+    #[test]
+    fn match_requires_the_exact_members() {
+        let presets = [preset("Downstairs", &["living", "kitchen"])];
+
+        assert!(match_preset(&presets, &[zone("zone_living", &["living", "kitchen", "study"])]).is_none());
+        assert!(match_preset(&presets, &[zone("zone_living", &["living"])]).is_none());
+    }
+
+    #[test]
+    fn activate_leads_with_the_volume_master() {
+        let mut downstairs = preset("Downstairs", &["living", "kitchen"]);
+
+        downstairs.volume_master = Some("kitchen".to_owned());
+
+        let outputs = outputs(&[("living", 20.0), ("kitchen", 20.0)]);
+        let commands = activate(&[downstairs], &["living", "kitchen"], &[], &outputs, SafetyCap::default());
+
+        assert_eq!(commands, vec![group(&["kitchen", "living"])]);
+    }
+
+    #[test]
+    fn activate_staggers_the_members() {
+        let mut house = preset("House", &["living", "kitchen", "study"]);
+
+        house.stagger_ms = Some(500);
+
+        let outputs = outputs(&[("living", 20.0), ("kitchen", 20.0), ("study", 20.0)]);
+        let commands = activate(&[house], &["living", "kitchen", "study"], &[], &outputs, SafetyCap::default());
+
+        assert_eq!(commands, vec![
+            group(&["living", "kitchen"]),
+            TransportCommand::Wait(Duration::from_millis(500)),
+            group(&["living", "kitchen", "study"])
+        ]);
+    }
+
+    #[test]
+    fn selection_maps_only_the_next_index_to_new() {
+        let mut settings = GroupingSettings {
+            presets: vec![preset("Downstairs", &["living", "kitchen"]), preset("Upstairs", &["study"])],
+            ..Default::default()
+        };
+
+        assert_eq!(settings.selection(), PresetSelection::None);
+
+        settings.selected = Some(1);
+        assert_eq!(settings.selection(), PresetSelection::Existing(1));
+
+        settings.selected = Some(2);
+        assert_eq!(settings.selection(), PresetSelection::New);
+
+        settings.selected = Some(3);
+        assert_eq!(settings.selection(), PresetSelection::None);
+    }
+
+    #[test]
+    fn activate_delays_volumes_after_grouping() {
+        let mut downstairs = with_volumes(preset("Downstairs", &["living", "kitchen"]), &[("living", 30.0)]);
+
+        downstairs.volume_delay_ms = Some(200);
+
+        let outputs = outputs(&[("living", 20.0), ("kitchen", 20.0)]);
+        let commands = activate(&[downstairs], &["living", "kitchen"], &[], &outputs, SafetyCap::default());
+
+        assert_eq!(commands, vec![
+            group(&["living", "kitchen"]),
+            TransportCommand::Wait(Duration::from_millis(200)),
+            change_volume("living", 30.0)
+        ]);
+    }
+
+    #[test]
+    fn match_prefers_the_highest_priority() {
+        let mut evening = preset("Evening", &["living", "kitchen"]);
+
+        evening.priority = 2;
+
+        let presets = [preset("Downstairs", &["living", "kitchen"]), evening];
+        let zones = [zone("zone_living", &["living", "kitchen"])];
+
+        assert_eq!(match_preset(&presets, &zones).unwrap().0.name, "Evening");
+    }
+
+    #[test]
+    fn activate_sets_volumes_then_groups_with_the_primary_first() {
+        let presets = [with_volumes(preset("Downstairs", &["living", "kitchen"]), &[("living", 30.0), ("kitchen", 40.0)])];
+        let outputs = outputs(&[("living", 20.0), ("kitchen", 20.0)]);
+        let commands = activate(&presets, &["kitchen", "living"], &[], &outputs, SafetyCap::default());
+
+        assert_eq!(commands, vec![
+            change_volume("kitchen", 40.0),
+            change_volume("living", 30.0),
+            group(&["living", "kitchen"])
+        ]);
+    }
+
+    #[test]
+    fn activate_ungroups_zones_holding_members() {
+        let presets = [preset("Downstairs", &["living", "kitchen"])];
+        let outputs = outputs(&[("living", 20.0), ("kitchen", 20.0), ("study", 20.0)]);
+        let zones = [zone("zone_kitchen", &["kitchen", "study"]), zone("zone_living", &["living"])];
+        let commands = activate(&presets, &["living", "kitchen"], &zones, &outputs, SafetyCap::default());
+
+        assert_eq!(commands, vec![
+            TransportCommand::Ungroup(vec!["kitchen".to_owned(), "study".to_owned()]),
+            group(&["living", "kitchen"])
+        ]);
+    }
+
+    #[test]
+    fn deactivate_ungroups_the_live_zone() {
+        let presets = [preset("Downstairs", &["living", "kitchen"])];
+        let outputs = outputs(&[("living", 20.0), ("kitchen", 20.0), ("study", 20.0)]);
+        let zones = [zone("zone_living", &["kitchen", "living", "study"])];
+        let mut input = PlanInput {
+            presets: &presets,
+            selected: 0,
+            extracted_preset: None,
+            output_ids: &["living", "kitchen"],
+            zones: &zones,
+            outputs: &outputs,
+            last_volumes: &HashMap::new(),
+            safety_cap: SafetyCap::default()
+        };
+
+        // The group has drifted, the study joined it
+        assert_eq!(plan_action(&Action::Deactivate, &input), vec![
+            TransportCommand::Ungroup(vec!["kitchen".to_owned(), "living".to_owned(), "study".to_owned()])
+        ]);
+
+        input.zones = &[];
+
+        assert_eq!(plan_action(&Action::Deactivate, &input), vec![
+            TransportCommand::Ungroup(vec!["living".to_owned(), "kitchen".to_owned()])
+        ]);
+    }
+
+    #[test]
+    fn safety_cap_limits_within_its_bounds() {
+        let volume = output("living", 20.0).volume.unwrap();
+        let safety_cap = SafetyCap { min_percent: Some(10), max_percent: Some(50) };
+
+        assert_eq!(safety_cap.limit(&volume, 80.0), 50.0);
+        assert_eq!(safety_cap.limit(&volume, 5.0), 10.0);
+        assert_eq!(safety_cap.limit(&volume, 30.0), 30.0);
+        assert_eq!(SafetyCap::default().limit(&volume, 80.0), 80.0);
+    }
+
+    #[test]
+    fn safety_cap_is_engaged_only_where_it_changed_a_level() {
+        // The kitchen sits on the bound already, it needs no limiting
+        let downstairs = with_volumes(
+            preset("Downstairs", &["living", "kitchen", "study"]),
+            &[("living", 80.0), ("kitchen", 50.0), ("study", 30.0)]
+        );
+        let outputs = outputs(&[("living", 20.0), ("kitchen", 20.0), ("study", 20.0)]);
+        let output_ids = ["living", "kitchen", "study"];
+        let safety_cap = SafetyCap { min_percent: None, max_percent: Some(50) };
+
+        assert_eq!(safety_cap.engaged(&downstairs, &output_ids, &outputs, &HashMap::new(), &HashMap::new()), vec!["living".to_owned()]);
+        assert_eq!(plan_volumes(&downstairs, &output_ids, &outputs, &HashMap::new(), safety_cap), vec![
+            change_volume("living", 50.0),
+            change_volume("kitchen", 50.0),
+            change_volume("study", 30.0)
+        ]);
+    }
+
+    #[test]
+    fn safety_cap_is_read_from_the_settings() {
+        let settings = GroupingSettings {
+            safety_min: " 5 ".to_owned(),
+            safety_max: String::new(),
+            ..Default::default()
+        };
+        let safety_cap = settings.safety_cap();
+
+        assert_eq!(safety_cap.min_percent, Some(5));
+        assert_eq!(safety_cap.max_percent, None);
+    }
+
+    #[test]
+    fn fit_clamps_levels_and_their_percent() {
+        let mut downstairs = with_volumes(preset("Downstairs", &["living", "kitchen"]), &[("living", 120.0), ("kitchen", 40.0)]);
+
+        downstairs.volume_percents.insert("living".to_owned(), 120.0);
+        downstairs.volume_profiles.insert("Loud".to_owned(), HashMap::from([("kitchen".to_owned(), -10.0)]));
+
+        let mut presets = [downstairs];
+        let adjusted = fit_preset_volumes(&mut presets, &outputs(&[("living", 20.0), ("kitchen", 20.0)]));
+
+        assert_eq!(adjusted, 2);
+        assert_eq!(presets[0].volumes["living"], 100.0);
+        assert_eq!(presets[0].volumes["kitchen"], 40.0);
+        assert_eq!(presets[0].volume_percents["living"], 100.0);
+        assert_eq!(presets[0].volume_profiles["Loud"]["kitchen"], 0.0);
+    }
+
+    #[test]
+    fn fit_leaves_absent_outputs_alone() {
+        let mut presets = [with_volumes(preset("Garden", &["garden"]), &[("garden", 120.0)])];
+
+        assert_eq!(fit_preset_volumes(&mut presets, &outputs(&[("living", 20.0)])), 0);
+        assert_eq!(presets[0].volumes["garden"], 120.0);
+    }
+
+    #[test]
+    fn mirror_follows_the_primary() {
+        let mut downstairs = preset("Downstairs", &["living", "kitchen", "study"]);
+
+        downstairs.mirror = true;
+
+        let outputs = outputs(&[("living", 50.0), ("kitchen", 20.0), ("study", 50.0)]);
+        let commands = plan_mirror(&downstairs, &["living".to_owned()], &outputs, SafetyCap::default());
+
+        // The study is already there
+        assert_eq!(commands, vec![change_volume("kitchen", 50.0)]);
+    }
+
+    #[test]
+    fn mirror_ignores_other_changes() {
+        let mut downstairs = preset("Downstairs", &["living", "kitchen"]);
+        let outputs = outputs(&[("living", 50.0), ("kitchen", 20.0)]);
+
+        assert!(plan_mirror(&downstairs, &["living".to_owned()], &outputs, SafetyCap::default()).is_empty());
+
+        downstairs.mirror = true;
+
+        assert!(plan_mirror(&downstairs, &["kitchen".to_owned()], &outputs, SafetyCap::default()).is_empty());
+    }
+
+    #[test]
+    fn mirror_stays_within_the_safety_cap() {
+        let mut downstairs = preset("Downstairs", &["living", "kitchen"]);
+        let safety_cap = SafetyCap { min_percent: None, max_percent: Some(40) };
+
+        downstairs.mirror = true;
+
+        let outputs = outputs(&[("living", 80.0), ("kitchen", 20.0)]);
+
+        assert_eq!(plan_mirror(&downstairs, &["living".to_owned()], &outputs, safety_cap), vec![change_volume("kitchen", 40.0)]);
+    }
+
+    #[test]
+    fn cooldown_counts_down_from_the_last_activation() {
+        let mut downstairs = preset("Downstairs", &["living", "kitchen"]);
+        let mut last_activations = HashMap::new();
+
+        assert_eq!(cooldown_left(&downstairs, &last_activations), None);
+
+        downstairs.cooldown_seconds = Some(60);
+        assert_eq!(cooldown_left(&downstairs, &last_activations), None);
+
+        last_activations.insert("Downstairs".to_owned(), Instant::now());
+        assert!(matches!(cooldown_left(&downstairs, &last_activations), Some(59..=60)));
+
+        if let Some(long_ago) = Instant::now().checked_sub(Duration::from_secs(120)) {
+            last_activations.insert("Downstairs".to_owned(), long_ago);
+            assert_eq!(cooldown_left(&downstairs, &last_activations), None);
+        }
+    }
+
+    // Settings and outputs for a layout, without the callbacks that normally hold them
+    struct Preview {
+        settings: GroupingSettings,
+        outputs: HashMap<String, Output>,
+        zones: HashMap<String, Zone>
+    }
+
+    impl Preview {
+        fn new(presets: Vec<Preset>) -> Self {
+            Preview {
+                settings: GroupingSettings { presets, ..Default::default() },
+                outputs: outputs(&[("living", 20.0), ("kitchen", 20.0), ("study", 20.0)]),
+                zones: HashMap::new()
+            }
+        }
+
+        fn selected(mut self, index: usize) -> Self {
+            self.settings.selected = Some(index);
+            self
+        }
+
+        fn action(mut self, action: Action) -> Self {
+            self.settings.action = action;
+            self
+        }
+
+        fn volume_type(mut self, volume_type: VolumeType) -> Self {
+            self.settings.volume_type = volume_type;
+            self
+        }
+
+        fn advanced(mut self) -> Self {
+            self.settings.advanced_mode = true;
+            self
+        }
+
+        fn without_outputs(mut self) -> Self {
+            self.outputs.clear();
+            self
+        }
+
+        fn layout(self) -> Layout<GroupingSettings> {
+            make_layout(self.settings, &self.outputs, &self.zones)
+        }
+    }
+
+    // The settings behind the widgets, including those within groups
+    fn shown_settings(widgets: &[Widget]) -> Vec<String> {
+        widgets
+            .iter()
+            .flat_map(|widget| match widget {
+                Widget::Dropdown(dropdown) => vec![dropdown.setting.to_string()],
+                Widget::Textbox(textbox) => vec![textbox.setting.to_string()],
+                Widget::Integer(integer) => vec![integer.setting.to_string()],
+                Widget::Group(group) => shown_settings(&group.items),
+                _ => Vec::new()
+            })
+            .collect()
+    }
+
+    fn offered_actions(widgets: &[Widget]) -> Vec<String> {
+        widgets
+            .iter()
+            .filter_map(|widget| match widget {
+                Widget::Dropdown(dropdown) if dropdown.setting == "action" => Some(&dropdown.values),
+                _ => None
+            })
+            .flatten()
+            .filter_map(|value| value.get("title")?.as_str().map(|title| title.to_owned()))
+            .collect()
+    }
+
+    fn downstairs() -> Vec<Preset> {
+        vec![with_volumes(preset("Downstairs", &["living", "kitchen"]), &[("living", 30.0)])]
+    }
+
+    #[test]
+    fn layout_waits_for_outputs() {
+        let layout = Preview::new(downstairs()).without_outputs().layout();
+
+        assert_eq!(layout.widgets.len(), 1);
+        assert!(matches!(layout.widgets[0], Widget::Label(_)));
+    }
+
+    #[test]
+    fn layout_offers_actions_only_for_an_existing_preset() {
+        let settings = shown_settings(&Preview::new(downstairs()).layout().widgets);
+
+        assert!(settings.contains(&"selected".to_owned()));
+        assert!(!settings.contains(&"action".to_owned()));
+
+        let settings = shown_settings(&Preview::new(downstairs()).selected(0).layout().widgets);
+
+        assert!(settings.contains(&"action".to_owned()));
+
+        let settings = shown_settings(&Preview::new(downstairs()).selected(1).layout().widgets);
+
+        assert!(!settings.contains(&"action".to_owned()));
+    }
+
+    #[test]
+    fn layout_shows_the_editor_on_edit() {
+        let settings = shown_settings(&Preview::new(downstairs()).selected(0).action(Action::Activate).layout().widgets);
+
+        assert!(!settings.contains(&"name".to_owned()));
+
+        let settings = shown_settings(&Preview::new(downstairs()).selected(0).action(Action::Edit).layout().widgets);
+
+        assert!(settings.contains(&"name".to_owned()));
+        assert!(!settings.contains(&"auto_name".to_owned()));
+
+        let settings = shown_settings(&Preview::new(downstairs()).selected(0).action(Action::Edit).advanced().layout().widgets);
+
+        assert!(settings.contains(&"auto_name".to_owned()));
+    }
+
+    #[test]
+    fn layout_offers_volume_actions_for_the_volume_type() {
+        let copy_volume = "Copy Volume to All".to_owned();
+        let apply_volumes = "Apply Volumes Only".to_owned();
+
+        let actions = offered_actions(&Preview::new(downstairs()).selected(0).advanced().layout().widgets);
+
+        assert!(!actions.contains(&copy_volume));
+        assert!(!actions.contains(&apply_volumes));
+
+        let actions = offered_actions(&Preview::new(downstairs()).selected(0).volume_type(VolumeType::Preset).layout().widgets);
+
+        // Simple mode keeps the list short
+        assert!(!actions.contains(&copy_volume));
+
+        let actions = offered_actions(&Preview::new(downstairs()).selected(0).volume_type(VolumeType::Preset).advanced().layout().widgets);
+
+        assert!(actions.contains(&copy_volume));
+        assert!(actions.contains(&apply_volumes));
+
+        let actions = offered_actions(&Preview::new(downstairs()).selected(0).volume_type(VolumeType::LastUsed).advanced().layout().widgets);
+
+        assert!(!actions.contains(&copy_volume));
+        assert!(actions.contains(&apply_volumes));
+    }
+
+    #[test]
+    fn half_steps_are_stored_and_sent_as_such() {
+        let outputs = HashMap::from([("living".to_owned(), db_output("living", 0.5, -30.0))]);
+        let volume = outputs["living"].volume.as_ref().unwrap();
+        let mut settings = GroupingSettings {
+            presets: vec![with_volumes(preset("Living", &["living"]), &[("living", -30.0)])],
+            selected: Some(0),
+            output_ids: vec!["living".to_owned()],
+            volume_type: VolumeType::Preset,
+            volume_output_id: Some("living".to_owned()),
+            volume_level: "-20.5".to_owned(),
+            ..Default::default()
+        };
+
+        assert_eq!(check_volume_level(volume, "-20.5"), None);
+        assert!(check_volume_level(volume, "-20.25").is_some());
+        assert_eq!(store_volume(&mut settings, &outputs), Ok(()));
+        assert_eq!(settings.presets[0].volumes["living"], -20.5);
+        assert_eq!(volume_requests(-20.5, 0.5), vec![("absolute", -21), ("relative_step", 1)]);
+        assert_eq!(volume_requests(-20.0, 0.5), vec![("absolute", -20)]);
+        assert_eq!(volume_requests(30.0, 1.0), vec![("absolute", 30)]);
+    }
+
+    fn find_integer<'a>(widgets: &'a [Widget], setting: &str) -> Option<&'a Integer> {
+        widgets.iter().find_map(|widget| match widget {
+            Widget::Integer(integer) if integer.setting == setting => Some(integer),
+            Widget::Group(group) => find_integer(&group.items, setting),
+            _ => None
+        })
+    }
+
+    #[test]
+    fn db_outputs_show_their_unit_and_range() {
+        let mut preview = Preview::new(vec![with_volumes(preset("Living", &["living"]), &[("living", -30.0)])])
+            .selected(0)
+            .volume_type(VolumeType::Preset);
+
+        preview.outputs = HashMap::from([("living".to_owned(), db_output("living", 1.0, -30.0))]);
+        preview.settings.output_ids = vec!["living".to_owned()];
+        preview.settings.volume_output_id = Some("living".to_owned());
+        preview.settings.volume_level = "-30".to_owned();
+
+        let volume = db_output("living", 1.0, -30.0).volume.unwrap();
+        let widgets = preview.layout().widgets;
+        let volume_level = find_integer(&widgets, "volume_level").unwrap();
+
+        assert_eq!(volume_level.subtitle.as_deref(), Some("Volume level in dB"));
+        assert_eq!((volume_level.min.as_str(), volume_level.max.as_str()), ("-80", "0"));
+        assert_eq!(volume_level.error, None);
+        assert_eq!(check_volume_level(&volume, "-90"), Some("Volume level should be between -80dB and 0dB".to_owned()));
+        assert_eq!(check_volume_level(&volume, "-30"), None);
+    }
+
+    #[tokio::test]
+    async fn volumes_are_changed_concurrently() {
+        let commands = [change_volume("living", 30.0), change_volume("kitchen", 40.0), change_volume("study", 50.0)];
+        let sent = Mutex::new(Vec::new());
+        let started = tokio::time::Instant::now();
+
+        change_volumes(&commands, |output_id, how, value| {
+            sent.lock().unwrap().push((output_id.to_owned(), how, value));
+
+            // A slow transport, every request takes a while to be answered
+            tokio::time::sleep(Duration::from_millis(100))
+        }).await;
+
+        assert!(started.elapsed() < Duration::from_millis(250));
+        assert_eq!(sent.into_inner().unwrap(), vec![
+            ("living".to_owned(), "absolute", 30),
+            ("kitchen".to_owned(), "absolute", 40),
+            ("study".to_owned(), "absolute", 50)
+        ]);
+    }
+
+    #[test]
+    fn triggers_keep_usage_and_captured_volumes_only() {
+        let mut saved = GroupingSettings {
+            presets: vec![preset("Downstairs", &["living", "kitchen"])],
+            name: "Editing".to_owned(),
+            ..Default::default()
+        };
+        let mut triggered = saved.to_owned();
+
+        triggered.selected = Some(0);
+        triggered.name = "Downstairs".to_owned();
+        triggered.presets[0].activation_count = 3;
+        triggered.presets[0].volumes = HashMap::from([("living".to_owned(), 30.0), ("kitchen".to_owned(), 40.0)]);
+        triggered.presets[0].volume_percents = HashMap::from([("living".to_owned(), 30.0)]);
+        keep_usage(&mut saved, &triggered);
+
+        assert_eq!(saved.name, "Editing");
+        assert_eq!(saved.selected, None);
+        assert_eq!(saved.presets[0].activation_count, 3);
+        assert_eq!(saved.presets[0].volumes, triggered.presets[0].volumes);
+        assert_eq!(saved.presets[0].volume_percents, triggered.presets[0].volume_percents);
+    }
+
+    #[test]
+    fn volumes_go_to_the_selected_profile() {
+        let mut downstairs = with_volumes(preset("Downstairs", &["living", "kitchen"]), &[("living", 30.0)]);
+
+        downstairs.volume_profiles.insert("Night".to_owned(), HashMap::from([("living".to_owned(), 10.0)]));
+        downstairs.active_profile = Some("Night".to_owned());
+
+        let outputs = outputs(&[("living", 20.0), ("kitchen", 20.0)]);
+        let mut settings = GroupingSettings {
+            presets: vec![downstairs],
+            selected: Some(0),
+            volume_output_id: Some("living".to_owned()),
+            ..Default::default()
+        };
+
+        load_preset(&mut settings, &outputs);
+        assert_eq!(settings.volume_level, "10");
+
+        settings.volume_level = "15".to_owned();
+        assert_eq!(store_volume(&mut settings, &outputs), Ok(()));
+        assert_eq!(settings.presets[0].volume_profiles["Night"]["living"], 15.0);
+        assert_eq!(settings.presets[0].volumes["living"], 30.0);
+
+        settings.presets[0].active_profile = None;
+        load_volume_level(&mut settings, &outputs);
+        assert_eq!(settings.volume_level, "30");
+    }
+
+    #[test]
+    fn batches_plan_with_the_zones_left_by_the_previous_preset() {
+        let presets = [preset("Downstairs", &["living", "kitchen"]), preset("Work", &["kitchen", "study"])];
+        let outputs = outputs(&[("living", 20.0), ("kitchen", 20.0), ("study", 20.0)]);
+        let mut zones = vec![zone("zone_living", &["living"]), zone("zone_kitchen", &["kitchen"]), zone("zone_study", &["study"])];
+        let commands = activate(&presets, &["living", "kitchen"], &zones, &outputs, SafetyCap::default());
+
+        apply_commands(&mut zones, &commands);
+
+        let members: Vec<Vec<&str>> = zones.iter().map(zone_members).collect();
+
+        assert_eq!(members, vec![vec!["study"], vec!["living", "kitchen"]]);
+        assert_eq!(activate(&presets[1..], &["kitchen", "study"], &zones, &outputs, SafetyCap::default()), vec![
+            TransportCommand::Ungroup(vec!["living".to_owned(), "kitchen".to_owned()]),
+            group(&["kitchen", "study"])
+        ]);
+
+        apply_commands(&mut zones, &[TransportCommand::Ungroup(vec!["living".to_owned(), "kitchen".to_owned()])]);
+
+        let members: Vec<Vec<&str>> = zones.iter().map(zone_members).collect();
+
+        assert_eq!(members, vec![vec!["study"], vec!["living"], vec!["kitchen"]]);
+        assert_eq!(zones[1].zone_id, "zone_living");
+    }
+
+    #[test]
+    fn deactivate_captures_volumes_of_the_live_group_only() {
+        let downstairs = preset("Downstairs", &["living", "kitchen"]);
+        let outputs = outputs(&[("living", 30.0), ("kitchen", 40.0), ("study", 50.0)]);
+
+        assert_eq!(captured_volumes(&downstairs, &[zone("zone_study", &["study", "garden"])], &outputs), vec![]);
+        assert_eq!(captured_volumes(&downstairs, &[zone("zone_living", &["living", "kitchen"])], &outputs), vec![
+            ("living".to_owned(), 30.0),
+            ("kitchen".to_owned(), 40.0)
+        ]);
+    }
+
+    #[test]
+    fn store_errors_are_shown_on_their_widget() {
+        let mut preview = Preview::new(downstairs()).selected(1).action(Action::Edit);
+
+        preview.settings.primary_output_id = Some("living".to_owned());
+
+        let err = store_preset(&mut preview.settings, &preview.outputs).unwrap_err();
+
+        assert_eq!(err, StoreError::NoName);
+        assert_eq!(preview.settings.presets.len(), 1);
+
+        preview.settings.store_errors = vec![(err.setting().to_owned(), err.message().to_owned())];
+
+        let layout = preview.layout();
+        let edit_group = layout.widgets.iter().find_map(|widget| match widget {
+            Widget::Group(group) if shown_settings(&group.items).contains(&"name".to_owned()) => Some(group),
+            _ => None
+        }).unwrap();
+        let labels: Vec<&str> = edit_group.items.iter().filter_map(|widget| match widget {
+            Widget::Label(label) => Some(label.title.as_str()),
+            _ => None
+        }).collect();
+
+        assert!(layout.has_error);
+        assert!(labels.contains(&"Enter a name to save the preset"));
+    }
+
+    #[test]
+    fn outputs_only_grouping_with_themselves_are_no_primary() {
+        let mut preview = Preview::new(Vec::new()).selected(0).action(Action::Edit);
+        let mut study = output("study", 20.0);
+
+        study.can_group_with_output_ids = vec!["study".to_owned()];
+        preview.outputs.insert("study".to_owned(), study);
+        preview.settings.name = "Downstairs".to_owned();
+
+        let layout = preview.layout();
+        let primary_values: Vec<&Value> = layout.widgets.iter().find_map(|widget| match widget {
+            Widget::Group(group) => group.items.iter().find_map(|item| match item {
+                Widget::Dropdown(dropdown) if dropdown.setting == "primary_output_id" => Some(&dropdown.values),
+                _ => None
+            }),
+            _ => None
+        }).unwrap().iter().filter_map(|value| value.get("value")).collect();
+
+        assert!(primary_values.contains(&&json!("living")));
+        assert!(!primary_values.contains(&&json!("study")));
+    }
+
+    #[test]
+    fn messages_name_outputs_by_their_alias() {
+        let presets = [preset("Downstairs", &["living", "kitchen"]), preset("Work", &["kitchen", "study"])];
+        let outputs = outputs(&[("living", 20.0), ("kitchen", 20.0), ("study", 20.0)]);
+        let aliases = HashMap::from([("kitchen".to_owned(), "Cooking".to_owned())]);
+        let zones = [zone("zone_living", &["living", "kitchen", "study"])];
+
+        assert_eq!(find_overlaps(&presets, 0, &outputs, &aliases), vec![
+            "Presets \"Downstairs\" and \"Work\" both use Cooking; only one can be active at a time".to_owned()
+        ]);
+        assert_eq!(find_near_misses(&presets[1..], zones.iter(), &aliases), vec![
+            "Zone \"zone_living\" nearly matches \"Work\" (differs by output living)".to_owned()
+        ]);
+
+        let everywhere = [preset("Everywhere", &["living", "kitchen", "study"])];
+
+        assert_eq!(find_near_misses(&everywhere, [zone("zone_living", &["living", "study"])].iter(), &aliases), vec![
+            "Zone \"zone_living\" nearly matches \"Everywhere\" (differs by output Cooking)".to_owned()
+        ]);
+    }
+
+    #[test]
+    fn renaming_a_zone_refreshes_the_cached_layout() {
+        let preview = Preview::new(downstairs());
+        let mut zones = HashMap::from([("zone_living".to_owned(), zone("zone_living", &["living", "kitchen"]))]);
+        let mut cache = None;
+
+        cached_layout(&mut cache, preview.settings.to_owned(), &preview.outputs, &zones);
+
+        let key = cache.as_ref().map(|(key, _)| *key);
+
+        cached_layout(&mut cache, preview.settings.to_owned(), &preview.outputs, &zones);
+        assert_eq!(cache.as_ref().map(|(key, _)| *key), key);
+
+        if let Some(zone) = zones.get_mut("zone_living") {
+            zone.display_name = "Downstairs".to_owned();
+        }
+
+        cached_layout(&mut cache, preview.settings.to_owned(), &preview.outputs, &zones);
+        assert_ne!(cache.as_ref().map(|(key, _)| *key), key);
+    }
+
+    #[test]
+    fn ungroup_all_dissolves_every_group_regardless_of_the_presets() {
+        let zones = [zone("zone_living", &["living", "kitchen"]), zone("zone_study", &["study"]), zone("zone_garden", &["garden", "patio"])];
+
+        assert_eq!(plan_ungroup_all(&zones), vec![
+            TransportCommand::Ungroup(vec!["living".to_owned(), "kitchen".to_owned()]),
+            TransportCommand::Ungroup(vec!["garden".to_owned(), "patio".to_owned()])
+        ]);
+    }
+
+    #[test]
+    fn adding_the_primary_again_stores_it_once() {
+        let outputs = outputs(&[("living", 20.0), ("kitchen", 20.0)]);
+        let mut settings = GroupingSettings {
+            presets: downstairs(),
+            selected: Some(0),
+            name: "Downstairs".to_owned(),
+            primary_output_id: Some("living".to_owned()),
+            output_ids: vec!["kitchen".to_owned(), "living".to_owned(), "kitchen".to_owned()],
+            add: Some("living".to_owned()),
+            ..Default::default()
+        };
+
+        assert_eq!(store_preset(&mut settings, &outputs), Ok(()));
+        assert_eq!(settings.presets[0].output_ids, vec!["living".to_owned(), "kitchen".to_owned()]);
+        assert_eq!(settings.output_ids, settings.presets[0].output_ids);
+    }
+
+    #[test]
+    fn volume_levels_survive_a_store_and_reload() {
+        let outputs = outputs(&[("living", 20.0), ("kitchen", 20.0)]);
+        let mut settings = GroupingSettings {
+            presets: vec![with_volumes(preset("Downstairs", &["living", "kitchen"]), &[("living", 0.0)])],
+            selected: Some(0),
+            volume_output_id: Some("living".to_owned()),
+            ..Default::default()
+        };
+
+        // A level of 0 is stored, it is not taken from the live level
+        load_preset(&mut settings, &outputs);
+        assert_eq!(settings.volume_level, "0");
+        assert_eq!(store_volume(&mut settings, &outputs), Ok(()));
+        assert_eq!(settings.presets[0].volumes["living"], 0.0);
+
+        for (entered, reloaded) in [("35", "35"), ("007", "7"), (" 42 ", "42"), ("0", "0")] {
+            settings.volume_level = entered.to_owned();
+            assert_eq!(store_volume(&mut settings, &outputs), Ok(()));
+
+            load_preset(&mut settings, &outputs);
+            assert_eq!(settings.volume_level, reloaded);
+        }
+
+        settings.volume_level = String::new();
+        assert_eq!(store_volume(&mut settings, &outputs), Err(StoreError::InvalidVolumeLevel));
+    }
+
+    #[test]
+    fn volume_output_left_by_a_membership_change_is_cleared() {
+        let outputs = outputs(&[("living", 20.0), ("kitchen", 20.0)]);
+        let mut settings = GroupingSettings {
+            presets: downstairs(),
+            selected: Some(0),
+            output_ids: vec!["kitchen".to_owned()],
+            volume_type: VolumeType::Preset,
+            volume_output_id: Some("living".to_owned()),
+            volume_level: "50".to_owned(),
+            ..Default::default()
+        };
+
+        assert_eq!(store_volume(&mut settings, &outputs), Ok(()));
+        assert_eq!(settings.volume_output_id, None);
+        assert!(settings.volume_output_cleared);
+        assert_eq!(settings.presets[0].volumes["living"], 30.0);
+
+        // A member without a stored level starts from its live one
+        settings.volume_output_id = Some("kitchen".to_owned());
+        assert_eq!(store_volume(&mut settings, &outputs), Ok(()));
+        assert!(!settings.volume_output_cleared);
+        assert_eq!(settings.presets[0].volumes["kitchen"], 20.0);
+    }
+
+    #[test]
+    fn volume_type_is_kept_through_the_save_cycle() {
+        let outputs = outputs(&[("living", 20.0), ("kitchen", 20.0)]);
+        let mut settings = GroupingSettings {
+            presets: downstairs(),
+            selected: Some(0),
+            ..Default::default()
+        };
+
+        load_preset(&mut settings, &outputs);
+
+        // Without a volume output selected, as is the case right after picking the type
+        for volume_type in [VolumeType::Untouched, VolumeType::LastUsed, VolumeType::Preset, VolumeType::Untouched] {
+            let expected = volume_type.to_owned() as usize;
+
+            settings.volume_type = volume_type;
+            settings.volume_output_id = None;
+            assert_eq!(store_preset(&mut settings, &outputs), Ok(()));
+            assert_eq!(store_volume(&mut settings, &outputs), Ok(()));
+            assert_eq!(settings.presets[0].volume_type.to_owned() as usize, expected);
+
+            load_preset(&mut settings, &outputs);
+            assert_eq!(settings.volume_type.to_owned() as usize, expected);
+        }
+    }
+
+    #[test]
+    fn saving_an_edit_only_stores_the_preset() {
+        let outputs = outputs(&[("living", 20.0), ("kitchen", 20.0)]);
+        let mut presets = downstairs();
+
+        presets[0].activation_count = 3;
+
+        let mut settings = GroupingSettings { presets, selected: Some(0), ..Default::default() };
+
+        load_preset(&mut settings, &outputs);
+        settings.name = "Ground Floor".to_owned();
+
+        assert!(!settings.action.is_execution());
+        assert_eq!(store_preset(&mut settings, &outputs), Ok(()));
+        assert_eq!(settings.presets[0].name, "Ground Floor");
+        assert_eq!(settings.presets[0].activation_count, 3);
+
+        let executed: Vec<usize> = (0..=21)
+            .filter_map(|action| serde_json::from_value::<Action>(json!(action)).ok())
+            .filter(|action| action.is_execution())
+            .map(|action| action as usize)
+            .collect();
+
+        assert_eq!(executed, vec![
+            Action::Activate as usize,
+            Action::Deactivate as usize,
+            Action::UngroupAll as usize,
+            Action::ApplyVolumes as usize,
+            Action::ActivateBatch as usize,
+            Action::ToggleMute as usize
+        ]);
+    }
+
+    #[test]
+    fn fades_ramp_each_output_in_its_own_direction() {
+        let outputs = outputs(&[("living", 20.0), ("kitchen", 80.0)]);
+        let commands = ramp_volumes(&[change_volume("living", 40.0), change_volume("kitchen", 60.0)], &outputs, 1000);
+        let levels = |output_id: &str| -> Vec<f32> {
+            commands
+                .iter()
+                .filter_map(|command| match command {
+                    TransportCommand::ChangeVolume(id, level, _) if id == output_id => Some(*level),
+                    _ => None
+                })
+                .collect()
+        };
+        let waits = commands.iter().filter(|command| matches!(command, TransportCommand::Wait(_))).count();
+
+        assert_eq!(levels("living"), vec![22.0, 24.0, 26.0, 28.0, 30.0, 32.0, 34.0, 36.0, 38.0, 40.0]);
+        assert_eq!(levels("kitchen"), vec![78.0, 76.0, 74.0, 72.0, 70.0, 68.0, 66.0, 64.0, 62.0, 60.0]);
+        assert_eq!(waits, FADE_STEPS as usize - 1);
+        assert_eq!(commands.last(), Some(&change_volume("kitchen", 60.0)));
+    }
+
+    #[test]
+    fn reloading_a_preset_keeps_the_chosen_action() {
+        let outputs = outputs(&[("living", 20.0), ("kitchen", 20.0)]);
+        let mut presets = downstairs();
+
+        presets.push(preset("Empty", &[]));
+
+        let mut settings = GroupingSettings { presets, selected: Some(0), action: Action::Activate, ..Default::default() };
+
+        load_preset(&mut settings, &outputs);
+        assert!(matches!(settings.action, Action::Activate));
+
+        settings.selected = Some(1);
+        load_preset(&mut settings, &outputs);
+        assert!(matches!(settings.action, Action::Activate));
+
+        // A new preset can only be edited
+        settings.selected = Some(2);
+        load_preset(&mut settings, &outputs);
+        assert!(matches!(settings.action, Action::Edit));
+    }
+
+    #[test]
+    fn new_presets_without_a_name_are_not_appended() {
+        let outputs = outputs(&[("living", 20.0), ("kitchen", 20.0)]);
+        let mut settings = GroupingSettings {
+            presets: downstairs(),
+            selected: Some(1),
+            primary_output_id: Some("living".to_owned()),
+            add: Some("kitchen".to_owned()),
+            ..Default::default()
+        };
+
+        for name in ["", "   "] {
+            settings.name = name.to_owned();
+            assert_eq!(store_preset(&mut settings, &outputs), Err(StoreError::NoName));
+            assert_eq!(settings.presets.len(), 1);
+            assert_eq!(settings.selected, Some(1));
+        }
+
+        settings.name = "Work".to_owned();
+        assert_eq!(store_preset(&mut settings, &outputs), Ok(()));
+        assert_eq!(settings.presets.len(), 2);
+    }
+
+    #[test]
+    fn zone_split_when_a_member_leaves() {
+        let presets = downstairs();
+        let matched = MatchedZone {
+            zone_id: "zone_living".to_owned(),
+            zone_name: "Living".to_owned(),
+            preset_name: "Downstairs".to_owned(),
+            master_name: None,
+            status_msg: String::new()
+        };
+
+        assert!(!is_split(&matched, &presets, &[zone("zone_living", &["living", "kitchen"])]));
+        assert!(is_split(&matched, &presets, &[zone("zone_living", &["living"]), zone("zone_kitchen", &["kitchen"])]));
+        assert!(!is_split(&matched, &presets, &[zone("zone_study", &["study"])]));
     }
 }